@@ -1,16 +1,20 @@
-extern crate rlua;
 extern crate zenroom_minimal;
 
-use rlua::Result;
 use zenroom_minimal::{
-    prelude::*, util::read_file, FileScenarioLinker, ScenarioLoader, ZencodeRuntime,
+    prelude::*, util::read_file, FileScenarioLinker, Result, ScenarioLoader, ZencodeRuntime,
 };
 
 fn main() -> Result<()> {
     let loader = ScenarioLoader::new(FileScenarioLinker::new("lua/examples/scenarios"));
     let mut runtime = ZencodeRuntime::new(loader);
     let zencode = read_file("lua/examples/helloworld.zencode")?;
-    let result = runtime.load(&zencode)?.eval()?;
-    println!("{:?}", result);
+    let result = runtime.load(&zencode)?.execute()?;
+    print!("{}", result.stdout);
+    println!("{:?}", result.output);
+    println!("{:?}", result.timings);
+    if let Some(traceback) = result.traceback {
+        println!("\n---\n");
+        println!("{}", traceback);
+    }
     Ok(())
 }
@@ -21,5 +21,5 @@ And print all data
 ZEN:parse(script)
 ZEN:run({}, {})
 
--- print("\n---\n")
--- print(ZEN_traceback)
+print("\n---\n")
+print(ZEN_traceback)
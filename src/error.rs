@@ -0,0 +1,265 @@
+use crate::Phase;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+use std::sync::Arc;
+
+/// The error type returned by `zenroom_minimal`.
+///
+/// Errors raised inside Rust callbacks are passed through the Lua VM as
+/// external errors, so the original variant is recovered when the error
+/// reaches the caller of a runtime.
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// A value could not be encoded or decoded (base64, utf-8, JSON)
+    Encoding(String),
+    /// A cryptographic operation failed or a key was rejected
+    Crypto(String),
+    /// The requested scenario could not be found by the
+    /// [`ScenarioLinker`](crate::ScenarioLinker). Contains the name of the
+    /// scenario and the locations which were tried, if the linker has any
+    ScenarioNotFound(String, Vec<String>),
+    /// The scenario name can not be used by the
+    /// [`ScenarioLinker`](crate::ScenarioLinker), for example because it
+    /// contains a path separator
+    InvalidScenarioName(String),
+    /// Scenarios depend on each other. Contains the chain of scenarios
+    /// which were loading, starting and ending with the same scenario
+    ScenarioCycle(Vec<String>),
+    /// A scenario does not match the version or SHA-256 hash it was
+    /// pinned to
+    ScenarioMismatch(String),
+    /// A scenario is not signed or its signature is not valid for any of
+    /// the trusted keys of the
+    /// [`VerifyingScenarioLinker`](crate::VerifyingScenarioLinker)
+    ScenarioSignature(String),
+    /// Zencode source could not be parsed
+    ZencodeParse(ZencodeError),
+    /// A Zencode statement failed while running
+    ZencodeRun(ZencodeError),
+    /// Reading from or writing to the file system failed
+    Io(Arc<io::Error>),
+    /// Any other error raised by the Lua VM
+    Lua(rlua::Error),
+    /// An execution limit of the runtime was exceeded
+    Limit(Limit),
+    /// The configuration of a [`RuntimeBuilder`](crate::RuntimeBuilder) or
+    /// a [`ScenarioLinker`](crate::ScenarioLinker) can not be used
+    Config(String),
+}
+
+/// The execution limit which was exceeded by a runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// The instruction budget was used up
+    Instructions,
+    /// The deadline for the execution passed
+    Timeout,
+    /// The Lua VM could not allocate more memory
+    Memory,
+}
+
+/// Details of an error in a Zencode script. The location is only known
+/// if the failing statement could be found in the script
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ZencodeError {
+    /// The underlying error message
+    pub message: String,
+    /// The 1-based line number of the statement in the script
+    pub line: Option<usize>,
+    /// The statement as written in the script
+    pub statement: Option<String>,
+    /// The phase of the statement
+    pub phase: Option<Phase>,
+    /// The scenario of the statement. For parse errors this is the
+    /// scenario declared last before the statement
+    pub scenario: Option<String>,
+    /// The registered statement which is closest to an unknown statement
+    pub suggestion: Option<String>,
+}
+
+impl ZencodeError {
+    /// Create a new [`ZencodeError`] without a location
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        ZencodeError {
+            message: message.into(),
+            ..ZencodeError::default()
+        }
+    }
+}
+
+/// A specialized [`Result`](std::result::Result) type for `zenroom_minimal`
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    fn find_external(err: &rlua::Error) -> Option<Error> {
+        match err {
+            rlua::Error::ExternalError(cause) => cause.downcast_ref::<Error>().cloned(),
+            rlua::Error::CallbackError { cause, .. } => Error::find_external(cause),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encoding(msg) => write!(f, "encoding error: {}", msg),
+            Error::Crypto(msg) => write!(f, "crypto error: {}", msg),
+            Error::ScenarioNotFound(name, tried) if tried.is_empty() => {
+                write!(f, "scenario '{}' could not be found", name)
+            }
+            Error::ScenarioNotFound(name, tried) => write!(
+                f,
+                "scenario '{}' could not be found, tried {}",
+                name,
+                tried.join(", ")
+            ),
+            Error::InvalidScenarioName(name) => write!(f, "invalid scenario name '{}'", name),
+            Error::ScenarioCycle(chain) => {
+                write!(f, "scenario dependency cycle: {}", chain.join(" -> "))
+            }
+            Error::ScenarioMismatch(msg) => write!(f, "scenario mismatch: {}", msg),
+            Error::ScenarioSignature(msg) => write!(f, "scenario signature error: {}", msg),
+            Error::ZencodeParse(err) => write!(f, "zencode parse error: {}", err),
+            Error::ZencodeRun(err) => write!(f, "zencode runtime error: {}", err),
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::Lua(err) => write!(f, "lua error: {}", err),
+            Error::Limit(limit) => write!(f, "limit exceeded: {}", limit),
+            Error::Config(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl fmt::Display for ZencodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(ref statement) = self.statement {
+            write!(f, "'{}': ", statement)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(ref scenario) = self.scenario {
+            write!(f, " (scenario '{}')", scenario)?;
+        }
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, ", did you mean '{}'?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions => write!(f, "instruction budget"),
+            Limit::Timeout => write!(f, "execution deadline"),
+            Limit::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(err) => Some(err.as_ref()),
+            Error::Lua(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rlua::Error> for Error {
+    fn from(err: rlua::Error) -> Self {
+        if let Some(err) = Error::find_external(&err) {
+            return err;
+        }
+        match err {
+            rlua::Error::MemoryError(_) => Error::Limit(Limit::Memory),
+            err => Error::Lua(err),
+        }
+    }
+}
+
+impl From<Error> for rlua::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Lua(err) => err,
+            err => rlua::Error::external(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(Arc::new(err))
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Self {
+        Error::Encoding(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Encoding(err.to_string())
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(err: FromUtf8Error) -> Self {
+        Error::Encoding(err.to_string())
+    }
+}
+
+impl From<ring::error::Unspecified> for Error {
+    fn from(err: ring::error::Unspecified) -> Self {
+        Error::Crypto(err.to_string())
+    }
+}
+
+impl From<ring::error::KeyRejected> for Error {
+    fn from(err: ring::error::KeyRejected) -> Self {
+        Error::Crypto(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlua::Lua;
+
+    #[test]
+    fn lua_round_trip() {
+        let lua = Lua::new();
+        let res: Result<()> = lua
+            .context(|ctx| {
+                let fail = ctx.create_function(|_, ()| -> rlua::Result<()> {
+                    Err(Error::Crypto("bad key".to_string()).into())
+                })?;
+                ctx.globals().set("fail", fail)?;
+                ctx.load("fail()").exec()
+            })
+            .map_err(Error::from);
+        match res {
+            Err(Error::Crypto(ref msg)) if msg == "bad key" => (),
+            res => panic!("expected crypto error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn lua_error_is_kept() {
+        let lua = Lua::new();
+        let res: Result<()> = lua
+            .context(|ctx| ctx.load("error('boom')").exec())
+            .map_err(Error::from);
+        match res {
+            Err(Error::Lua(_)) => (),
+            res => panic!("expected lua error, got {:?}", res),
+        }
+    }
+}
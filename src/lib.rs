@@ -27,16 +27,13 @@
 //! and provides instead an `import` function for whitelisted modules.
 //!
 //! ```
-//! # use zenroom_minimal::{prelude::*, DefaultRuntime, Result, Error};
+//! # use zenroom_minimal::{prelude::*, DefaultRuntime, Result};
 //! # fn main() -> Result<()> {
 //! let res = DefaultRuntime::default()
 //!     .load("return 'Hello, world!'")?
 //!     .eval()?;
-//! # if Some("Hello, world!".to_string()) == res {
-//! #   Ok(())
-//! # } else {
-//! #   Err(Error::RuntimeError(format!("Unexpected output: {:?}", res)))
-//! # }
+//! # assert_eq!(Some("Hello, world!".to_string()), res);
+//! # Ok(())
 //! # }
 //! ```
 //!
@@ -49,26 +46,30 @@
 #![warn(missing_docs)]
 
 extern crate base64;
+extern crate log;
 extern crate ring;
 extern crate rlua;
 extern crate rlua_serde;
 extern crate untrusted;
 
+mod error;
 mod module;
 mod runtime;
 
 /// Utility functions
 pub mod util;
 
+pub use error::{Error, Limit, Result, ZencodeError};
 pub use module::{
-    DefaultModule, FileScenarioLinker, Importer, Json, Keyring, KeyringClass, Module, Octet,
-    OctetClass, ScenarioLinker, ScenarioLoader, Zencode,
+    ChainScenarioLinker, DefaultModule, EmbeddedScenarioLinker, FileScenarioLinker, Importer, Json,
+    Keyring, KeyringClass, MapScenarioLinker, Module, Octet, OctetClass, OverlayScenarioLinker,
+    Phase, PrefixScenarioLinker, RandomSource, ScenarioLinker, ScenarioLoader, Scope,
+    SystemRandomSource, VerifyingScenarioLinker, WatchingScenarioLinker, Zencode,
+};
+pub use runtime::{
+    DefaultRuntime, ExecutionResult, Limits, MemoryUsage, ResourceUsage, RuntimeBuilder, Timings,
+    ZencodeRuntime,
 };
-pub use runtime::{DefaultRuntime, ZencodeRuntime};
-
-// TODO add own error types
-pub use rlua::Error;
-pub use rlua::Result;
 
 /// Useful traits for implementing the `zenroom_minimal` library
 pub mod prelude {
@@ -1,14 +1,138 @@
 use super::{DefaultModule, Json, KeyringClass, Module, OctetClass, Zencode};
-use rlua::{Context, Error, Result, Value};
+use rlua::{Context, Error, Result, Table, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Registry key of the table caching the imported modules of a Lua VM
+const LOADED_KEY: &str = "zenroom_minimal.import.loaded";
+
+type ModuleBuilder = Arc<dyn for<'lua> Fn(Context<'lua>) -> Result<Value<'lua>> + Send + Sync>;
+
+fn module_builder<F>(build: F) -> ModuleBuilder
+where
+    F: 'static + Send + Sync + for<'lua> Fn(Context<'lua>) -> Result<Value<'lua>>,
+{
+    Arc::new(build)
+}
 
 /// A module which imports another module by its [`Module::IDENTIFIER`].
 ///
-/// Available under `import("module_name")` in Lua as default
-#[derive(Default)]
-pub struct Importer;
+/// Available under `import("module_name")` in Lua as default. The
+/// [`Importer`] is a registry of importable modules: the default registry
+/// contains the builtin [`Json`], [`KeyringClass`], [`OctetClass`] and
+/// [`Zencode`] modules, and any other [`Module`] can be registered with
+/// [`Importer::register`].
+///
+/// Like `package.loaded` in Lua, each module is only built once per Lua VM
+/// and every later import returns the same value. Use
+/// [`Importer::invalidate`] to rebuild a module on its next import.
+///
+/// ```
+/// # use zenroom_minimal::{Importer, Zencode, prelude::*};
+/// let importer = Importer::default().remove(Zencode::IDENTIFIER);
+/// assert!(!importer.contains(Zencode::IDENTIFIER));
+/// ```
+#[derive(Clone)]
+pub struct Importer {
+    modules: HashMap<String, ModuleBuilder>,
+}
+
+impl Default for Importer {
+    fn default() -> Self {
+        Importer::empty()
+            .register::<Json>()
+            .register::<KeyringClass>()
+            .register::<OctetClass>()
+            .register::<Zencode>()
+    }
+}
 
 impl<'lua> Importer {
-    fn import(ctx: Context<'lua>, value: Value<'lua>) -> Result<Value<'lua>> {
+    /// Create an [`Importer`] without any importable modules
+    pub fn empty() -> Self {
+        Importer {
+            modules: HashMap::new(),
+        }
+    }
+
+    /// Create an [`Importer`] which can only import the builtin modules with
+    /// the given identifiers
+    pub fn only<I, S>(identifiers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Importer::default().retain(identifiers)
+    }
+
+    /// Register a [`Module`] to be importable under its
+    /// [`Module::IDENTIFIER`]. A new instance is created with
+    /// [`Default::default`] on every import
+    pub fn register<M: Module + Default>(self) -> Self {
+        self.register_with(M::default)
+    }
+
+    /// Register a [`Module`] to be importable under its
+    /// [`Module::IDENTIFIER`]. A new instance is created with `factory`
+    /// on every import
+    pub fn register_with<M, F>(mut self, factory: F) -> Self
+    where
+        M: Module,
+        F: 'static + Send + Sync + Fn() -> M,
+    {
+        self.modules.insert(
+            M::IDENTIFIER.to_string(),
+            module_builder(move |ctx| factory().build_module(ctx)),
+        );
+        self
+    }
+
+    /// Remove a module from the importable modules
+    pub fn remove(mut self, identifier: &str) -> Self {
+        self.modules.remove(identifier);
+        self
+    }
+
+    /// Only keep the modules with the given identifiers importable
+    pub fn retain<I, S>(mut self, identifiers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let identifiers: Vec<S> = identifiers.into_iter().collect();
+        self.modules
+            .retain(|id, _| identifiers.iter().any(|keep| keep.as_ref() == id.as_str()));
+        self
+    }
+
+    /// Whether a module with the identifier can be imported
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.modules.contains_key(identifier)
+    }
+
+    /// Remove a module from the import cache of the Lua VM, so that it is
+    /// built again on the next import
+    pub fn invalidate(ctx: Context<'lua>, identifier: &str) -> Result<()> {
+        Importer::loaded(ctx)?.set(identifier, Value::Nil)
+    }
+
+    /// Remove all modules from the import cache of the Lua VM
+    pub fn invalidate_all(ctx: Context<'lua>) -> Result<()> {
+        ctx.set_named_registry_value(LOADED_KEY, ctx.create_table()?)
+    }
+
+    fn loaded(ctx: Context<'lua>) -> Result<Table<'lua>> {
+        match ctx.named_registry_value::<_, Option<Table>>(LOADED_KEY)? {
+            Some(loaded) => Ok(loaded),
+            None => {
+                let loaded = ctx.create_table()?;
+                ctx.set_named_registry_value(LOADED_KEY, loaded.clone())?;
+                Ok(loaded)
+            }
+        }
+    }
+
+    fn import(&self, ctx: Context<'lua>, value: Value<'lua>) -> Result<Value<'lua>> {
         let name = match value {
             Value::String(s) => s,
             _ => {
@@ -17,16 +141,23 @@ impl<'lua> Importer {
                 ))
             }
         };
-        match name.to_str()? {
-            Json::IDENTIFIER => Json::default().build_module(ctx),
-            KeyringClass::IDENTIFIER => KeyringClass::default().build_module(ctx),
-            OctetClass::IDENTIFIER => OctetClass::default().build_module(ctx),
-            Zencode::IDENTIFIER => Zencode::default().build_module(ctx),
-            _ => Err(Error::RuntimeError(format!(
-                "module '{}' could not be found",
-                name.to_str()?
-            ))),
+        let name = name.to_str()?;
+        let build = match self.modules.get(name) {
+            Some(build) => build,
+            None => {
+                return Err(Error::RuntimeError(format!(
+                    "module '{}' could not be found",
+                    name
+                )))
+            }
+        };
+        let loaded = Importer::loaded(ctx)?;
+        if let Some(module) = loaded.get::<_, Option<Value>>(name)? {
+            return Ok(module);
         }
+        let module = build(ctx)?;
+        loaded.set(name, module.clone())?;
+        Ok(module)
     }
 }
 
@@ -34,10 +165,94 @@ impl Module for Importer {
     const IDENTIFIER: &'static str = "import";
 
     fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
-        Ok(Value::Function(ctx.create_function(Importer::import)?))
+        Ok(Value::Function(ctx.create_function(
+            move |ctx, value| self.import(ctx, value),
+        )?))
     }
 }
 
 impl DefaultModule for Importer {
     const GLOBAL_VAR: &'static str = "import";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlua::Lua;
+
+    struct Greeting(&'static str);
+
+    impl Module for Greeting {
+        const IDENTIFIER: &'static str = "greeting";
+
+        fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
+            Ok(Value::String(ctx.create_string(self.0)?))
+        }
+    }
+
+    fn import(importer: Importer, source: &str) -> Result<Option<String>> {
+        let lua = Lua::new();
+        lua.context(|ctx| {
+            ctx.globals()
+                .set(Importer::GLOBAL_VAR, importer.build_module(ctx)?)?;
+            ctx.load(source).eval()
+        })
+    }
+
+    #[test]
+    fn register_custom_module() -> Result<()> {
+        let importer = Importer::default().register_with(|| Greeting("hello"));
+        let res = import(importer, "return import('greeting')")?;
+        assert_eq!(res, Some("hello".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn remove_builtin() -> Result<()> {
+        let res = import(
+            Importer::default(),
+            "return tostring(import('json') ~= nil)",
+        )?;
+        assert_eq!(res, Some("true".to_string()));
+        let res = import(
+            Importer::default().remove(Json::IDENTIFIER),
+            "return tostring(import('json') ~= nil)",
+        );
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cached() -> Result<()> {
+        let res = import(
+            Importer::default(),
+            "return tostring(rawequal(import('json'), import('json')))",
+        )?;
+        assert_eq!(res, Some("true".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn invalidate() -> Result<()> {
+        let lua = Lua::new();
+        lua.context(|ctx| {
+            ctx.globals()
+                .set(Importer::GLOBAL_VAR, Importer::default().build_module(ctx)?)?;
+            ctx.load("first = import('json')").exec()?;
+            Importer::invalidate(ctx, Json::IDENTIFIER)?;
+            ctx.load("return rawequal(first, import('json'))").eval()
+        })
+        .and_then(|same: bool| {
+            assert!(!same);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn retain_builtins() {
+        let importer = Importer::only(&[OctetClass::IDENTIFIER]);
+        assert!(importer.contains(OctetClass::IDENTIFIER));
+        assert!(!importer.contains(KeyringClass::IDENTIFIER));
+        assert!(!importer.contains(Json::IDENTIFIER));
+    }
+}
@@ -1,5 +1,6 @@
 use super::{DefaultModule, Module};
-use rlua::{prelude::*, Context, Error, Result, Value};
+use crate::{Error, Result};
+use rlua::{prelude::*, Context, Value};
 
 /// Json encoding and decoding for Lua values.
 ///
@@ -10,35 +11,47 @@ use rlua::{prelude::*, Context, Error, Result, Value};
 pub struct Json;
 
 impl Json {
+    /// Convert a JSON value into a Lua value
+    pub(crate) fn to_lua<'lua>(
+        ctx: Context<'lua>,
+        value: &serde_json::Value,
+    ) -> Result<Value<'lua>> {
+        Ok(rlua_serde::to_value(ctx, value)?)
+    }
+
+    /// Convert a Lua value into a JSON value
+    pub(crate) fn from_lua(value: Value) -> Result<serde_json::Value> {
+        Ok(rlua_serde::from_value(value)?)
+    }
+
     fn encode<'lua>(ctx: Context<'lua>, value: Value<'lua>) -> Result<Value<'lua>> {
-        let json_value: serde_json::Value = rlua_serde::from_value(value)?;
-        let json =
-            serde_json::to_string(&json_value).map_err(|e| Error::RuntimeError(e.to_string()))?;
+        let json = serde_json::to_string(&Json::from_lua(value)?)?;
         Ok(json.to_lua(ctx)?)
     }
 
     fn decode<'lua>(ctx: Context<'lua>, value: Value<'lua>) -> Result<Value<'lua>> {
         let json = match value {
             Value::String(s) => s,
-            _ => {
-                return Err(Error::RuntimeError(
-                    "expecting string to decode".to_string(),
-                ))
-            }
+            _ => return Err(Error::Encoding("expecting string to decode".to_string())),
         };
-        let json_value: serde_json::Value =
-            serde_json::from_str(json.to_str()?).map_err(|e| Error::RuntimeError(e.to_string()))?;
-        Ok(rlua_serde::to_value(ctx, json_value)?)
+        let json_value: serde_json::Value = serde_json::from_str(json.to_str()?)?;
+        Json::to_lua(ctx, &json_value)
     }
 }
 
 impl Module for Json {
     const IDENTIFIER: &'static str = "json";
 
-    fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
+    fn build_module<'lua>(self, ctx: Context<'lua>) -> LuaResult<Value<'lua>> {
         let module = ctx.create_table()?;
-        module.set("encode", ctx.create_function(Json::encode)?)?;
-        module.set("decode", ctx.create_function(Json::decode)?)?;
+        module.set(
+            "encode",
+            ctx.create_function(|ctx, value| Ok(Json::encode(ctx, value)?))?,
+        )?;
+        module.set(
+            "decode",
+            ctx.create_function(|ctx, value| Ok(Json::decode(ctx, value)?))?,
+        )?;
         Ok(Value::Table(module))
     }
 }
@@ -1,6 +1,7 @@
 use super::{DefaultModule, Module, Octet};
+use crate::Result;
 use ring::{rand, signature, signature::EcdsaKeyPair, signature::KeyPair};
-use rlua::{Context, Error, Result, UserData, UserDataMethods, Value, Variadic};
+use rlua::{prelude::*, Context, UserData, UserDataMethods, Value, Variadic};
 
 /// A public / private Keypair. At the moment, this Keypair is only configured
 /// for the NIST256 curve. This may change in the future however.
@@ -32,6 +33,15 @@ impl Keyring {
         Keyring::default()
     }
 
+    /// Create a new Keyring which only has a public key, to verify
+    /// signatures
+    pub fn from_public(public: Octet) -> Self {
+        Keyring {
+            public,
+            private: Octet::default(),
+        }
+    }
+
     /// Create a new Keyring and generate a private / public keypair
     pub fn new_generated() -> Result<Self> {
         let mut keyring = Keyring::new();
@@ -41,18 +51,16 @@ impl Keyring {
 
     fn generate_private(&mut self) -> Result<()> {
         let rng = rand::SystemRandom::new();
-        let doc = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+        let doc = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)?;
         self.private = doc.as_ref().to_vec().into();
         Ok(())
     }
 
     fn keypair(&self) -> Result<EcdsaKeyPair> {
-        EcdsaKeyPair::from_pkcs8(
+        Ok(EcdsaKeyPair::from_pkcs8(
             &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
             untrusted::Input::from(self.private.as_ref()),
-        )
-        .map_err(|e| Error::RuntimeError(e.to_string()))
+        )?)
     }
 
     fn generate_public(&mut self) -> Result<()> {
@@ -65,8 +73,7 @@ impl Keyring {
         let rng = rand::SystemRandom::new();
         Ok(self
             .keypair()?
-            .sign(&rng, untrusted::Input::from(message.as_ref()))
-            .unwrap()
+            .sign(&rng, untrusted::Input::from(message.as_ref()))?
             .as_ref()
             .to_vec()
             .into())
@@ -120,19 +127,19 @@ impl UserData for Keyring {
         methods.add_method_mut("generate", |_, this, ()| Ok(this.generate()?));
         methods.add_method_mut("public", |_, this, vals: Variadic<Octet>| {
             if vals.len() > 0 {
-                this.set_public(vals[0].clone())
+                Ok(this.set_public(vals[0].clone())?)
             } else {
                 Ok(this.public().clone())
             }
         });
         methods.add_method_mut("private", |_, this, vals: Variadic<Octet>| {
             if vals.len() > 0 {
-                this.set_private(vals[0].clone())
+                Ok(this.set_private(vals[0].clone())?)
             } else {
                 Ok(this.private().clone())
             }
         });
-        methods.add_method("sign", |_, this, message| Ok(this.sign(&message)));
+        methods.add_method("sign", |_, this, message| Ok(this.sign(&message)?));
         methods.add_method("verify", |_, this, (message, signature)| {
             Ok(this.verify(&message, &signature))
         });
@@ -142,7 +149,7 @@ impl UserData for Keyring {
 impl Module for KeyringClass {
     const IDENTIFIER: &'static str = "keyring";
 
-    fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
+    fn build_module<'lua>(self, ctx: Context<'lua>) -> LuaResult<Value<'lua>> {
         let module = ctx.create_table()?;
         module.set("new", ctx.create_function(|_, ()| Ok(Keyring::new()))?)?;
         module.set(
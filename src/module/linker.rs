@@ -0,0 +1,939 @@
+use super::scenario::decode_signature;
+use super::{FileScenarioLinker, Keyring, Octet, ScenarioLinker};
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+type Linker = Box<dyn ScenarioLinker + Send + Sync>;
+type ErrorCallback = Box<dyn Fn(&str, &Error) + Send + Sync>;
+
+/// [`ScenarioLinker`] which reads scenarios from memory, for environments
+/// without a file system. The signature of a scenario is read from the
+/// entry named like the scenario with `.sig` appended
+///
+/// ```
+/// # use zenroom_minimal::MapScenarioLinker;
+/// let linker = MapScenarioLinker::new()
+///     .scenario("hello", "Given(\"that my name is ''\", function(name) end)");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MapScenarioLinker(HashMap<String, String>);
+
+impl MapScenarioLinker {
+    /// Create a new [`MapScenarioLinker`] without scenarios
+    pub fn new() -> Self {
+        MapScenarioLinker::default()
+    }
+
+    /// Add the Lua source of a scenario, replacing a scenario with the same
+    /// name
+    pub fn scenario<N: Into<String>, S: Into<String>>(mut self, name: N, source: S) -> Self {
+        self.0.insert(name.into(), source.into());
+        self
+    }
+}
+
+impl From<HashMap<String, String>> for MapScenarioLinker {
+    fn from(scenarios: HashMap<String, String>) -> Self {
+        MapScenarioLinker(scenarios)
+    }
+}
+
+impl ScenarioLinker for MapScenarioLinker {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.0
+            .get(scenario)
+            .cloned()
+            .ok_or_else(|| Error::ScenarioNotFound(scenario.to_string(), Vec::new()))
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        if !self.0.contains_key(scenario) {
+            return Err(Error::ScenarioNotFound(scenario.to_string(), Vec::new()));
+        }
+        match self.0.get(&format!("{}.sig", scenario)) {
+            Some(signature) => Ok(Some(decode_signature(signature)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// [`ScenarioLinker`] which reads scenarios compiled into the binary. Use
+/// [`EmbeddedScenarioLinker::generate`] in a build script and
+/// [`embedded_scenarios!`](crate::embedded_scenarios) to create it.
+/// Signatures are read from the entries named `<scenario>.sig`
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedScenarioLinker(&'static [(&'static str, &'static str)]);
+
+impl EmbeddedScenarioLinker {
+    /// Create a new [`EmbeddedScenarioLinker`] from pairs of scenario name
+    /// and Lua source
+    ///
+    /// ```
+    /// # use zenroom_minimal::{EmbeddedScenarioLinker, ScenarioLoader, ZencodeRuntime};
+    /// let linker = EmbeddedScenarioLinker::new(&[(
+    ///     "hello",
+    ///     include_str!(concat!(
+    ///         env!("CARGO_MANIFEST_DIR"),
+    ///         "/lua/examples/scenarios/zencode_hello.lua"
+    ///     )),
+    /// )]);
+    /// let runtime = ZencodeRuntime::new(ScenarioLoader::new(linker));
+    /// ```
+    pub const fn new(scenarios: &'static [(&'static str, &'static str)]) -> Self {
+        EmbeddedScenarioLinker(scenarios)
+    }
+
+    /// Write the table of the scenarios in `dir` to the file `out`, for
+    /// use in a build script. Every file named `zencode_<name>.lua` in the
+    /// directory is included as scenario `<name>` and every file named
+    /// `zencode_<name>.lua.sig` as its signature. The table is included
+    /// with [`embedded_scenarios!`](crate::embedded_scenarios), and Cargo
+    /// is told to run the build script again when the directory changes.
+    ///
+    /// ```no_run
+    /// // build.rs
+    /// # use zenroom_minimal::EmbeddedScenarioLinker;
+    /// use std::{env, path::Path};
+    ///
+    /// fn main() {
+    ///     let out = Path::new(&env::var("OUT_DIR").unwrap()).join("scenarios.rs");
+    ///     EmbeddedScenarioLinker::generate("scenarios", out).unwrap();
+    /// }
+    /// ```
+    pub fn generate<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, out: Q) -> Result<()> {
+        let dir = dir.as_ref().canonicalize()?;
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(file_name) if file_name.starts_with("zencode_") => file_name,
+                _ => continue,
+            };
+            let name = &file_name["zencode_".len()..];
+            let name = if name.ends_with(".lua.sig") {
+                format!("{}.sig", &name[..name.len() - ".lua.sig".len()])
+            } else if name.ends_with(".lua") {
+                name[..name.len() - ".lua".len()].to_string()
+            } else {
+                continue;
+            };
+            if name.is_empty() || name.starts_with('.') {
+                continue;
+            }
+            let path = path.to_str().ok_or_else(|| {
+                Error::Config(format!("scenario path {} is not unicode", path.display()))
+            })?;
+            entries.push((name, path.to_string()));
+        }
+        entries.sort();
+        let table = entries
+            .iter()
+            .map(|(name, path)| format!("    ({:?}, include_str!({:?})),\n", name, path))
+            .collect::<String>();
+        fs::write(out, format!("&[\n{}]\n", table))?;
+        println!("cargo:rerun-if-changed={}", dir.display());
+        Ok(())
+    }
+}
+
+impl EmbeddedScenarioLinker {
+    fn find(&self, name: &str) -> Option<&'static str> {
+        self.0
+            .iter()
+            .find(|(entry, _)| *entry == name)
+            .map(|(_, source)| *source)
+    }
+}
+
+impl ScenarioLinker for EmbeddedScenarioLinker {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.find(scenario)
+            .map(str::to_string)
+            .ok_or_else(|| Error::ScenarioNotFound(scenario.to_string(), Vec::new()))
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        if self.find(scenario).is_none() {
+            return Err(Error::ScenarioNotFound(scenario.to_string(), Vec::new()));
+        }
+        match self.find(&format!("{}.sig", scenario)) {
+            Some(signature) => Ok(Some(decode_signature(signature)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The result of reading a scenario which is needed to combine linkers
+enum Lookup<T> {
+    Found(T),
+    /// The scenario does not exist, with the locations which were tried
+    Missing(Vec<String>),
+    /// The name can not be used by the linker
+    Invalid,
+}
+
+impl<T> Lookup<T> {
+    fn new(res: Result<T>) -> Result<Lookup<T>> {
+        match res {
+            Ok(found) => Ok(Lookup::Found(found)),
+            Err(Error::ScenarioNotFound(_, tried)) => Ok(Lookup::Missing(tried)),
+            Err(Error::InvalidScenarioName(_)) => Ok(Lookup::Invalid),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The error for a scenario which no linker could read. The name is
+    /// only invalid if it is invalid for all linkers
+    fn missing<I: IntoIterator<Item = Lookup<T>>>(scenario: &str, lookups: I) -> Error {
+        let mut tried = Vec::new();
+        let mut invalid = true;
+        for lookup in lookups {
+            if let Lookup::Missing(mut locations) = lookup {
+                tried.append(&mut locations);
+                invalid = false;
+            }
+        }
+        if invalid {
+            Error::InvalidScenarioName(scenario.to_string())
+        } else {
+            Error::ScenarioNotFound(scenario.to_string(), tried)
+        }
+    }
+}
+
+/// [`ScenarioLinker`] which tries a list of linkers in order and reads the
+/// scenario from the first one which has it. Linkers which do not have the
+/// scenario or reject its name are skipped, any other error is returned.
+/// The signature is read from the same linker as the scenario
+///
+/// ```
+/// # use zenroom_minimal::{ChainScenarioLinker, FileScenarioLinker, MapScenarioLinker};
+/// let linker = ChainScenarioLinker::new()
+///     .link(FileScenarioLinker::new("custom"))
+///     .link(MapScenarioLinker::new().scenario("hello", "-- builtin"));
+/// ```
+#[derive(Default)]
+pub struct ChainScenarioLinker(Vec<Linker>);
+
+impl ChainScenarioLinker {
+    /// Create a new [`ChainScenarioLinker`] without linkers
+    pub fn new() -> Self {
+        ChainScenarioLinker::default()
+    }
+
+    /// Add a linker which is tried after the previous ones
+    pub fn link<L: 'static + ScenarioLinker + Send + Sync>(mut self, linker: L) -> Self {
+        self.0.push(Box::new(linker));
+        self
+    }
+}
+
+impl ChainScenarioLinker {
+    /// Read the scenario from the first linker which has it
+    fn find<T, F: Fn(&Linker) -> Result<T>>(&self, scenario: &str, read: F) -> Result<T> {
+        let mut lookups = Vec::with_capacity(self.0.len());
+        for linker in self.0.iter() {
+            match Lookup::new(read(linker))? {
+                Lookup::Found(found) => return Ok(found),
+                lookup => lookups.push(lookup),
+            }
+        }
+        Err(Lookup::missing(scenario, lookups))
+    }
+}
+
+impl ScenarioLinker for ChainScenarioLinker {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.find(scenario, |linker| linker.read_scenario(scenario))
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        self.read_signed_scenario(scenario)
+            .map(|(_, signature)| signature)
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        self.find(scenario, |linker| linker.read_signed_scenario(scenario))
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        self.0.iter().any(|linker| linker.is_modified(scenario))
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        for linker in self.0.iter() {
+            linker.reload_failed(scenario, err);
+        }
+    }
+}
+
+/// [`ScenarioLinker`] which routes scenarios by the prefix of their name,
+/// for example `std/hash` and `custom/hash`. The prefix is removed before
+/// the name is passed on, and the longest matching prefix wins. Names
+/// without a matching prefix are passed unchanged to the fallback linker,
+/// if there is one
+///
+/// ```
+/// # use zenroom_minimal::{FileScenarioLinker, MapScenarioLinker, PrefixScenarioLinker};
+/// let linker = PrefixScenarioLinker::new()
+///     .route("std/", MapScenarioLinker::new().scenario("hash", "-- builtin"))
+///     .route("custom/", FileScenarioLinker::new("custom"));
+/// ```
+#[derive(Default)]
+pub struct PrefixScenarioLinker {
+    routes: Vec<(String, Linker)>,
+    fallback: Option<Linker>,
+}
+
+impl PrefixScenarioLinker {
+    /// Create a new [`PrefixScenarioLinker`] without routes
+    pub fn new() -> Self {
+        PrefixScenarioLinker::default()
+    }
+
+    /// Read scenarios starting with `prefix` from `linker`
+    pub fn route<P, L>(mut self, prefix: P, linker: L) -> Self
+    where
+        P: Into<String>,
+        L: 'static + ScenarioLinker + Send + Sync,
+    {
+        self.routes.push((prefix.into(), Box::new(linker)));
+        self.routes.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+        self
+    }
+
+    /// Read scenarios without a matching prefix from `linker`
+    pub fn fallback<L: 'static + ScenarioLinker + Send + Sync>(mut self, linker: L) -> Self {
+        self.fallback = Some(Box::new(linker));
+        self
+    }
+}
+
+impl PrefixScenarioLinker {
+    /// The linker for a scenario and the name which is passed on to it
+    fn resolve<'a>(&'a self, scenario: &'a str) -> Result<(&'a str, &'a Linker)> {
+        let route = self
+            .routes
+            .iter()
+            .find(|(prefix, _)| scenario.starts_with(prefix.as_str()));
+        match (route, &self.fallback) {
+            (Some((prefix, linker)), _) => Ok((&scenario[prefix.len()..], linker)),
+            (None, Some(fallback)) => Ok((scenario, fallback)),
+            (None, None) => Err(Error::ScenarioNotFound(scenario.to_string(), Vec::new())),
+        }
+    }
+
+    /// Read the scenario from its linker. Errors name the scenario as it
+    /// was requested
+    fn read<T, F: Fn(&Linker, &str) -> Result<T>>(&self, scenario: &str, read: F) -> Result<T> {
+        let (name, linker) = self.resolve(scenario)?;
+        match Lookup::new(read(linker, name))? {
+            Lookup::Found(found) => Ok(found),
+            lookup => Err(Lookup::missing(scenario, vec![lookup])),
+        }
+    }
+}
+
+impl ScenarioLinker for PrefixScenarioLinker {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.read(scenario, |linker, name| linker.read_scenario(name))
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        self.read(scenario, |linker, name| linker.read_signature(name))
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        self.read(scenario, |linker, name| linker.read_signed_scenario(name))
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        match self.resolve(scenario) {
+            Ok((name, linker)) => linker.is_modified(name),
+            Err(_) => false,
+        }
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        if let Ok((name, linker)) = self.resolve(scenario) {
+            linker.reload_failed(name, err);
+        }
+    }
+}
+
+/// [`ScenarioLinker`] which lays the scenarios of one linker over those of
+/// another. If both have a scenario, the base scenario runs first and the
+/// overlay afterwards, so statements of the overlay replace base statements
+/// with the same pattern while the other base statements stay available.
+/// Each part runs in its own `do ... end` block, so locals do not clash.
+///
+/// The combined source does not start with the version comment of a part,
+/// so overlaid scenarios can only be pinned by their hash. For the same
+/// reason they have no signature: wrap the base and the overlay linker in
+/// a [`VerifyingScenarioLinker`] each to verify the parts instead
+#[derive(Clone, Debug)]
+pub struct OverlayScenarioLinker<B, O> {
+    base: B,
+    overlay: O,
+}
+
+impl<B, O> OverlayScenarioLinker<B, O>
+where
+    B: ScenarioLinker,
+    O: ScenarioLinker,
+{
+    /// Create a new [`OverlayScenarioLinker`] laying `overlay` over `base`
+    pub fn new(base: B, overlay: O) -> Self {
+        OverlayScenarioLinker { base, overlay }
+    }
+}
+
+impl<B, O> ScenarioLinker for OverlayScenarioLinker<B, O>
+where
+    B: ScenarioLinker,
+    O: ScenarioLinker,
+{
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        let base = Lookup::new(self.base.read_scenario(scenario))?;
+        let overlay = Lookup::new(self.overlay.read_scenario(scenario))?;
+        match (base, overlay) {
+            (Lookup::Found(base), Lookup::Found(overlay)) => {
+                Ok(format!("do\n{}\nend\ndo\n{}\nend\n", base, overlay))
+            }
+            (Lookup::Found(source), _) | (_, Lookup::Found(source)) => Ok(source),
+            (base, overlay) => Err(Lookup::missing(scenario, vec![base, overlay])),
+        }
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        self.base.is_modified(scenario) || self.overlay.is_modified(scenario)
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        self.base.reload_failed(scenario, err);
+        self.overlay.reload_failed(scenario, err);
+    }
+}
+
+/// [`ScenarioLinker`] which only returns scenarios with a valid detached
+/// signature of one of the trusted keys. Signatures are ECDSA P-256
+/// signatures of the scenario source like those of [`Keyring::sign`],
+/// stored as url safe base64 next to the scenario (see the linkers for
+/// where). Scenarios without a signature or with a signature which no
+/// trusted key verifies fail with [`Error::ScenarioSignature`] and are not
+/// run.
+///
+/// ```
+/// # use zenroom_minimal::{FileScenarioLinker, Keyring, VerifyingScenarioLinker};
+/// # let keyring = Keyring::new_generated().unwrap();
+/// let linker = VerifyingScenarioLinker::new(FileScenarioLinker::new("scenarios"))
+///     .trust_public(keyring.public().clone());
+/// ```
+#[derive(Clone, Debug)]
+pub struct VerifyingScenarioLinker<L> {
+    linker: L,
+    keys: Vec<Keyring>,
+}
+
+impl<L: ScenarioLinker> VerifyingScenarioLinker<L> {
+    /// Create a new [`VerifyingScenarioLinker`] for the scenarios of
+    /// `linker`, which trusts no key yet
+    pub fn new(linker: L) -> Self {
+        VerifyingScenarioLinker {
+            linker,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Trust signatures of the public key of `keyring`. The private key
+    /// is not kept
+    pub fn trust(self, keyring: &Keyring) -> Self {
+        self.trust_public(keyring.public().clone())
+    }
+
+    /// Trust signatures of a public key
+    pub fn trust_public(mut self, public: Octet) -> Self {
+        self.keys.push(Keyring::from_public(public));
+        self
+    }
+}
+
+impl<L: ScenarioLinker> ScenarioLinker for VerifyingScenarioLinker<L> {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.read_signed_scenario(scenario)
+            .map(|(source, _)| source)
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        self.linker.read_signature(scenario)
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        let (source, signature) = match self.linker.read_signed_scenario(scenario) {
+            Ok((source, Some(signature))) => (source, signature),
+            Ok((_, None)) => {
+                return Err(Error::ScenarioSignature(format!(
+                    "scenario '{}' is not signed",
+                    scenario
+                )))
+            }
+            Err(Error::Encoding(err)) => {
+                return Err(Error::ScenarioSignature(format!(
+                    "signature of scenario '{}' can not be decoded: {}",
+                    scenario, err
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+        let message = Octet::new(source.as_bytes().to_vec());
+        if self.keys.iter().any(|key| key.verify(&message, &signature)) {
+            Ok((source, Some(signature)))
+        } else {
+            Err(Error::ScenarioSignature(format!(
+                "signature of scenario '{}' is not valid for any trusted key",
+                scenario
+            )))
+        }
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        self.linker.is_modified(scenario)
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        self.linker.reload_failed(scenario, err)
+    }
+}
+
+/// [`ScenarioLinker`] which reads scenarios with a [`FileScenarioLinker`]
+/// and watches the files it read, to develop scenarios without restarting
+/// the service. A scenario is modified when its file or the signature next
+/// to it changed or was removed, or when another file takes precedence in
+/// the search paths now.
+/// The [`ScenarioLoader`](crate::ScenarioLoader) then runs the scenario
+/// again the next time Zencode requesting it is loaded, which replaces the
+/// statements it defines. Statements removed from the scenario stay
+/// registered.
+///
+/// The files are checked whenever a loaded scenario is requested, there is
+/// no background thread. Errors while running a modified scenario again are
+/// passed to the callback set with
+/// [`on_error`](WatchingScenarioLinker::on_error), or logged as warnings
+///
+/// ```
+/// # use zenroom_minimal::{FileScenarioLinker, ScenarioLoader, WatchingScenarioLinker, ZencodeRuntime};
+/// let linker = WatchingScenarioLinker::new(FileScenarioLinker::new("scenarios"))
+///     .on_error(|scenario, err| eprintln!("reloading '{}' failed: {}", scenario, err));
+/// let runtime = ZencodeRuntime::new(ScenarioLoader::new(linker));
+/// ```
+pub struct WatchingScenarioLinker {
+    linker: FileScenarioLinker,
+    files: Mutex<HashMap<String, Option<Stamp>>>,
+    on_error: Option<ErrorCallback>,
+}
+
+/// The file a scenario was read from, with its modification time and size
+#[derive(PartialEq)]
+struct Stamp {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+    /// The modification time and size of the signature file, if it exists
+    signature: Option<(Option<SystemTime>, u64)>,
+}
+
+impl WatchingScenarioLinker {
+    /// Create a new [`WatchingScenarioLinker`] watching the scenarios read
+    /// by `linker`
+    pub fn new(linker: FileScenarioLinker) -> Self {
+        WatchingScenarioLinker {
+            linker,
+            files: Mutex::new(HashMap::new()),
+            on_error: None,
+        }
+    }
+
+    /// Call `callback` with the name of the scenario and the error when a
+    /// modified scenario can not be run again
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: 'static + Fn(&str, &Error) + Send + Sync,
+    {
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// The first file of the scenario which exists, like the file the
+    /// [`FileScenarioLinker`] reads
+    fn stamp(&self, scenario: &str) -> Option<Stamp> {
+        self.linker
+            .candidates(scenario)
+            .into_iter()
+            .find_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let mut signature = path.clone().into_os_string();
+                signature.push(".sig");
+                let signature = fs::metadata(signature)
+                    .ok()
+                    .map(|signature| (signature.modified().ok(), signature.len()));
+                Some(Stamp {
+                    path,
+                    modified: metadata.modified().ok(),
+                    len: metadata.len(),
+                    signature,
+                })
+            })
+    }
+
+    /// Read the scenario with `read` and remember the file it was read from
+    fn watch<T, F: FnOnce() -> Result<T>>(&self, scenario: &str, read: F) -> Result<T> {
+        // the file is stamped before it is read, so a change while reading
+        // is found with the next check
+        let stamp = self.stamp(scenario);
+        let read = read()?;
+        self.files
+            .lock()
+            .unwrap()
+            .insert(scenario.to_string(), stamp);
+        Ok(read)
+    }
+}
+
+impl ScenarioLinker for WatchingScenarioLinker {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        self.watch(scenario, || self.linker.read_scenario(scenario))
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        self.linker.read_signature(scenario)
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        self.watch(scenario, || self.linker.read_signed_scenario(scenario))
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        let mut files = self.files.lock().unwrap();
+        match files.get_mut(scenario) {
+            Some(stamp) => {
+                let current = self.stamp(scenario);
+                // a change is only reported once, even if reading it fails
+                let modified = *stamp != current;
+                *stamp = current;
+                modified
+            }
+            None => false,
+        }
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        match self.on_error {
+            Some(ref callback) => callback(scenario, err),
+            None => log::warn!("reloading scenario '{}' failed: {}", scenario, err),
+        }
+    }
+}
+
+/// Create an [`EmbeddedScenarioLinker`] from the table of scenarios which
+/// [`EmbeddedScenarioLinker::generate`] wrote to `file` in the `OUT_DIR`
+/// of the build script, which bundles every scenario of a directory into
+/// the binary.
+///
+/// ```ignore
+/// # #[macro_use] extern crate zenroom_minimal;
+/// # use zenroom_minimal::{ScenarioLoader, ZencodeRuntime};
+/// let linker = embedded_scenarios!("scenarios.rs");
+/// let runtime = ZencodeRuntime::new(ScenarioLoader::new(linker));
+/// ```
+#[macro_export]
+macro_rules! embedded_scenarios {
+    ($file:literal) => {
+        $crate::EmbeddedScenarioLinker::new(include!(concat!(env!("OUT_DIR"), "/", $file)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{prelude::*, thread_rng};
+
+    fn random_dir() -> PathBuf {
+        let name: String = thread_rng()
+            .sample_iter(rand::distributions::Alphanumeric)
+            .take(10)
+            .collect();
+        std::env::temp_dir().join(format!("zencode_{}", name))
+    }
+
+    #[test]
+    fn map_linker() {
+        let mut scenarios = HashMap::new();
+        scenarios.insert("a".to_string(), "return 1".to_string());
+        let linker = MapScenarioLinker::from(scenarios).scenario("b", "return 2");
+        assert_eq!(linker.read_scenario("a").unwrap(), "return 1");
+        assert_eq!(linker.read_scenario("b").unwrap(), "return 2");
+        match linker.read_scenario("c") {
+            Err(Error::ScenarioNotFound(ref name, _)) => assert_eq!(name, "c"),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn embedded_linker() {
+        let linker = EmbeddedScenarioLinker::new(&[("hello", "return 1")]);
+        assert_eq!(linker.read_scenario("hello").unwrap(), "return 1");
+        match linker.read_scenario("missing") {
+            Err(Error::ScenarioNotFound(..)) => (),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn generate_embedded() {
+        let dir = random_dir();
+        fs::create_dir_all(&dir).unwrap();
+        for file in &[
+            "zencode_b.lua",
+            "zencode_a.lua",
+            "zencode_a.lua.sig",
+            "a.lua",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let out = dir.join("scenarios.rs");
+        EmbeddedScenarioLinker::generate(&dir, &out).unwrap();
+        let path = |file: &str| dir.canonicalize().unwrap().join(file).display().to_string();
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            format!(
+                "&[\n    (\"a\", include_str!({:?})),\n    (\"a.sig\", include_str!({:?})),\n    (\"b\", include_str!({:?})),\n]\n",
+                path("zencode_a.lua"),
+                path("zencode_a.lua.sig"),
+                path("zencode_b.lua")
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn chain_linker() {
+        let linker = ChainScenarioLinker::new()
+            .link(crate::FileScenarioLinker::new("/nonexistent"))
+            .link(
+                MapScenarioLinker::new()
+                    .scenario("a", "first")
+                    .scenario("a/b", "nested"),
+            )
+            .link(
+                MapScenarioLinker::new()
+                    .scenario("a", "second")
+                    .scenario("c", "third"),
+            );
+        assert_eq!(linker.read_scenario("a").unwrap(), "first");
+        assert_eq!(linker.read_scenario("a/b").unwrap(), "nested");
+        assert_eq!(linker.read_scenario("c").unwrap(), "third");
+        match linker.read_scenario("d") {
+            Err(Error::ScenarioNotFound(_, ref tried)) => {
+                assert_eq!(tried, &vec!["/nonexistent/zencode_d.lua".to_string()])
+            }
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+        match ChainScenarioLinker::new()
+            .link(crate::FileScenarioLinker::new("/nonexistent"))
+            .read_scenario("../d")
+        {
+            Err(Error::InvalidScenarioName(_)) => (),
+            res => panic!("expected invalid scenario name, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn prefix_linker() {
+        let linker = PrefixScenarioLinker::new()
+            .route(
+                "std/",
+                MapScenarioLinker::new().scenario("hash", "std hash"),
+            )
+            .route(
+                "std/v2/",
+                MapScenarioLinker::new().scenario("hash", "std v2 hash"),
+            )
+            .fallback(MapScenarioLinker::new().scenario("hash", "custom hash"));
+        assert_eq!(linker.read_scenario("std/hash").unwrap(), "std hash");
+        assert_eq!(linker.read_scenario("std/v2/hash").unwrap(), "std v2 hash");
+        assert_eq!(linker.read_scenario("hash").unwrap(), "custom hash");
+        match linker.read_scenario("std/sign") {
+            Err(Error::ScenarioNotFound(ref name, _)) => assert_eq!(name, "std/sign"),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+        match PrefixScenarioLinker::new().read_scenario("hash") {
+            Err(Error::ScenarioNotFound(..)) => (),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn overlay_linker() {
+        let linker = OverlayScenarioLinker::new(
+            MapScenarioLinker::new()
+                .scenario("a", "local x = 1")
+                .scenario("b", "base"),
+            MapScenarioLinker::new()
+                .scenario("a", "local x = 2")
+                .scenario("c", "overlay"),
+        );
+        assert_eq!(
+            linker.read_scenario("a").unwrap(),
+            "do\nlocal x = 1\nend\ndo\nlocal x = 2\nend\n"
+        );
+        assert_eq!(linker.read_scenario("b").unwrap(), "base");
+        assert_eq!(linker.read_scenario("c").unwrap(), "overlay");
+        match linker.read_scenario("d") {
+            Err(Error::ScenarioNotFound(..)) => (),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn overlay_replaces_statements() {
+        let linker = OverlayScenarioLinker::new(
+            MapScenarioLinker::new().scenario(
+                "hello",
+                r#"
+Given("that my name is ''", function(name) ACK.name = name end)
+Then("say hello", function() OUT = "Hello, " .. ACK.name end)
+"#,
+            ),
+            MapScenarioLinker::new().scenario(
+                "hello",
+                r#"Then("say hello", function() OUT = "Hi, " .. ACK.name end)"#,
+            ),
+        );
+        let mut runtime = crate::ZencodeRuntime::new(crate::ScenarioLoader::new(linker));
+        let res = runtime
+            .load("Scenario 'hello'\nGiven that my name is 'Julian'\nThen say hello")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(res, Some("\"Hi, Julian\"".to_string()));
+    }
+
+    fn sign(keyring: &Keyring, source: &str) -> String {
+        let signature = keyring.sign(&source.as_bytes().to_vec().into()).unwrap();
+        base64::encode_config(&signature[..], base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn verifying_linker() {
+        let keyring = Keyring::new_generated().unwrap();
+        let other = Keyring::new_generated().unwrap();
+        let scenarios = MapScenarioLinker::new()
+            .scenario("signed", "return 1")
+            .scenario("signed.sig", sign(&keyring, "return 1"))
+            .scenario("tampered", "return 2")
+            .scenario("tampered.sig", sign(&keyring, "return 1"))
+            .scenario("foreign", "return 3")
+            .scenario("foreign.sig", sign(&other, "return 3"))
+            .scenario("corrupt", "return 5")
+            .scenario("corrupt.sig", "not base64!")
+            .scenario("unsigned", "return 4");
+        let linker = VerifyingScenarioLinker::new(scenarios.clone())
+            .trust_public(Octet::new(vec![4; 65]))
+            .trust(&keyring);
+        assert_eq!(linker.read_scenario("signed").unwrap(), "return 1");
+        for name in &["tampered", "foreign", "corrupt", "unsigned"] {
+            match linker.read_scenario(name) {
+                Err(Error::ScenarioSignature(ref msg)) => assert!(msg.contains(name)),
+                res => panic!("expected signature error, got {:?}", res),
+            }
+        }
+        match linker.read_scenario("missing") {
+            Err(Error::ScenarioNotFound(..)) => (),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+        match VerifyingScenarioLinker::new(scenarios).read_scenario("signed") {
+            Err(Error::ScenarioSignature(_)) => (),
+            res => panic!("expected signature error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn combined_signatures() {
+        let keyring = Keyring::new_generated().unwrap();
+        let signed = MapScenarioLinker::new()
+            .scenario("a", "signed")
+            .scenario("a.sig", sign(&keyring, "signed"));
+        let unsigned = MapScenarioLinker::new()
+            .scenario("a", "unsigned")
+            .scenario("b", "unsigned");
+        let chain = ChainScenarioLinker::new()
+            .link(unsigned.clone())
+            .link(signed.clone());
+        assert!(chain.read_signature("a").unwrap().is_none());
+        let chain = ChainScenarioLinker::new()
+            .link(signed.clone())
+            .link(unsigned);
+        assert!(chain.read_signature("a").unwrap().is_some());
+        assert!(chain.read_signature("b").unwrap().is_none());
+        let prefix =
+            VerifyingScenarioLinker::new(PrefixScenarioLinker::new().route("std/", signed))
+                .trust(&keyring);
+        assert_eq!(prefix.read_scenario("std/a").unwrap(), "signed");
+        match prefix.read_signature("std/c") {
+            Err(Error::ScenarioNotFound(ref name, _)) => assert_eq!(name, "std/c"),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn watching_linker() {
+        let dir = random_dir();
+        let override_dir = dir.join("override");
+        fs::create_dir_all(&override_dir).unwrap();
+        let file = dir.join("zencode_watched.lua");
+        fs::write(&file, "return 1").unwrap();
+        let errors = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let reported = errors.clone();
+        let linker = WatchingScenarioLinker::new(FileScenarioLinker::new(&override_dir).path(&dir))
+            .on_error(move |scenario, err| {
+                reported
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {}", scenario, err))
+            });
+
+        assert!(!linker.is_modified("watched"));
+        assert_eq!(linker.read_scenario("watched").unwrap(), "return 1");
+        assert!(!linker.is_modified("watched"));
+        fs::write(&file, "return 22").unwrap();
+        assert!(linker.is_modified("watched"));
+        assert!(!linker.is_modified("watched"));
+        assert_eq!(linker.read_scenario("watched").unwrap(), "return 22");
+
+        fs::write(override_dir.join("zencode_watched.lua"), "return 3").unwrap();
+        assert!(linker.is_modified("watched"));
+        assert_eq!(linker.read_scenario("watched").unwrap(), "return 3");
+        fs::remove_dir_all(&override_dir).unwrap();
+        assert!(linker.is_modified("watched"));
+
+        let signature = dir.join("zencode_watched.lua.sig");
+        fs::write(&signature, "AAEC").unwrap();
+        assert!(linker.is_modified("watched"));
+        assert!(!linker.is_modified("watched"));
+        fs::write(&signature, "AAECAw").unwrap();
+        assert!(linker.is_modified("watched"));
+        fs::remove_file(&signature).unwrap();
+        assert!(linker.is_modified("watched"));
+
+        linker.reload_failed("watched", &Error::Crypto("failed".to_string()));
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec!["watched: crypto error: failed".to_string()]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
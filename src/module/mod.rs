@@ -2,7 +2,9 @@ mod default;
 mod importer;
 mod json;
 mod keyring;
+mod linker;
 mod octet;
+mod random;
 mod scenario;
 mod zencode;
 
@@ -10,9 +12,16 @@ pub use default::DefaultModule;
 pub use importer::Importer;
 pub use json::Json;
 pub use keyring::{Keyring, KeyringClass};
+pub use linker::{
+    ChainScenarioLinker, EmbeddedScenarioLinker, MapScenarioLinker, OverlayScenarioLinker,
+    PrefixScenarioLinker, VerifyingScenarioLinker, WatchingScenarioLinker,
+};
 pub use octet::{Octet, OctetClass};
+pub(crate) use random::set_random_source;
+pub use random::{RandomSource, SystemRandomSource};
+pub(crate) use scenario::current_scenario;
 pub use scenario::{FileScenarioLinker, ScenarioLinker, ScenarioLoader};
-pub use zencode::Zencode;
+pub use zencode::{Phase, Scope, Zencode};
 
 use rlua::{Context, Result, Value};
 
@@ -1,5 +1,6 @@
-use super::{DefaultModule, Module};
-use rlua::{prelude::*, Context, Error, Result, UserData, UserDataMethods, Value};
+use super::{random::fill_random, DefaultModule, Module};
+use crate::{Error, Result};
+use rlua::{prelude::*, Context, UserData, UserDataMethods, Value};
 use std::ops::{Deref, DerefMut};
 
 /// A Wrapper around a ByteString for use inside and outside of Lua
@@ -12,10 +13,11 @@ pub struct Octet(Vec<u8>);
 
 /// A [`Octet`] factory.
 ///
-/// Exposes a default `OCTET` module which can generate octets in three ways
+/// Exposes a default `OCTET` module which can generate octets in four ways
 /// * `OCTET.new()`: new empty octet
 /// * `OCTET.base64(<lua string>)`: new octet from url safe base64 string
 /// * `OCTET.string(<lua string>)`: new octet from utf-8 string
+/// * `OCTET.random(<lua integer>)`: new octet of random bytes with the given length
 #[derive(Default)]
 pub struct OctetClass;
 
@@ -55,14 +57,9 @@ impl Octet {
     fn from_base64(value: Value) -> Result<Self> {
         let input = match value {
             Value::String(s) => s,
-            _ => {
-                return Err(Error::RuntimeError(
-                    "expecting string to decode".to_string(),
-                ))
-            }
+            _ => return Err(Error::Encoding("expecting string to decode".to_string())),
         };
-        let bytes = base64::decode_config(input.to_str()?, base64::URL_SAFE_NO_PAD)
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+        let bytes = base64::decode_config(input.to_str()?, base64::URL_SAFE_NO_PAD)?;
         Ok(Octet::new(bytes))
     }
 
@@ -75,22 +72,25 @@ impl Octet {
     fn from_string(value: Value) -> Result<Self> {
         let input = match value {
             Value::String(s) => s,
-            _ => {
-                return Err(Error::RuntimeError(
-                    "expecting string to decode".to_string(),
-                ))
-            }
+            _ => return Err(Error::Encoding("expecting string to decode".to_string())),
         };
         Ok(Octet::new(input.to_str()?.to_string().into()))
     }
 
     fn to_string<'lua>(&self, ctx: Context<'lua>) -> Result<Value<'lua>> {
-        let string = std::string::String::from_utf8(self.0.clone())
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+        let string = std::string::String::from_utf8(self.0.clone())?;
         Ok(string.to_lua(ctx)?)
     }
 }
 
+impl Octet {
+    fn random(ctx: Context, len: usize) -> Result<Self> {
+        let mut bytes = vec![0; len];
+        fill_random(ctx, &mut bytes)?;
+        Ok(Octet::new(bytes))
+    }
+}
+
 impl UserData for Octet {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method("base64", |ctx, this, ()| Ok(this.to_base64(ctx)?));
@@ -101,7 +101,7 @@ impl UserData for Octet {
 impl Module for OctetClass {
     const IDENTIFIER: &'static str = "octet";
 
-    fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
+    fn build_module<'lua>(self, ctx: Context<'lua>) -> LuaResult<Value<'lua>> {
         let module = ctx.create_table()?;
         module.set("new", ctx.create_function(|_, ()| Ok(Octet::default()))?)?;
         module.set(
@@ -112,6 +112,10 @@ impl Module for OctetClass {
             "string",
             ctx.create_function(|_, value: Value| Ok(Octet::from_string(value)?))?,
         )?;
+        module.set(
+            "random",
+            ctx.create_function(|ctx, len: usize| Ok(Octet::random(ctx, len)?))?,
+        )?;
         Ok(Value::Table(module))
     }
 }
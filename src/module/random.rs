@@ -0,0 +1,55 @@
+use crate::{Error, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use rlua::{AnyUserData, Context, UserData};
+use std::sync::Arc;
+
+/// Registry key under which the [`RandomSource`] of a Lua VM is stored
+const REGISTRY_KEY: &str = "zenroom_minimal.random_source";
+
+/// A source of random bytes for modules running inside of a Lua VM.
+///
+/// Note that key generation and signing in [`Keyring`](crate::Keyring)
+/// always use the operating system's secure random number generator.
+pub trait RandomSource: Send + Sync {
+    /// Fill `dest` with random bytes
+    fn fill(&self, dest: &mut [u8]) -> Result<()>;
+}
+
+/// [`RandomSource`] backed by the operating system's secure random
+/// number generator
+pub struct SystemRandomSource(SystemRandom);
+
+impl Default for SystemRandomSource {
+    fn default() -> Self {
+        SystemRandomSource(SystemRandom::new())
+    }
+}
+
+impl RandomSource for SystemRandomSource {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        Ok(self.0.fill(dest)?)
+    }
+}
+
+struct RandomSourceData(Arc<dyn RandomSource>);
+
+impl UserData for RandomSourceData {}
+
+/// Use `source` for all random bytes requested by modules in the Lua VM
+pub(crate) fn set_random_source(ctx: Context, source: Arc<dyn RandomSource>) -> Result<()> {
+    Ok(ctx.set_named_registry_value(REGISTRY_KEY, RandomSourceData(source))?)
+}
+
+/// Fill `dest` with random bytes from the [`RandomSource`] of the Lua VM,
+/// falling back to the [`SystemRandomSource`]
+pub(crate) fn fill_random(ctx: Context, dest: &mut [u8]) -> Result<()> {
+    match ctx.named_registry_value::<_, Option<AnyUserData>>(REGISTRY_KEY)? {
+        Some(data) => {
+            let source = data
+                .borrow::<RandomSourceData>()
+                .map_err(|_| Error::Crypto("invalid random source".to_string()))?;
+            source.0.fill(dest)
+        }
+        None => SystemRandomSource::default().fill(dest),
+    }
+}
@@ -1,33 +1,225 @@
-use super::{DefaultModule, Module};
-use crate::util::read_file;
-use rlua::{Context, Error, Result, Value};
+use super::{DefaultModule, Module, Octet};
+use crate::util::{read_file, sha256_hex};
+use crate::{Error, Result};
+use rlua::{prelude::*, Context, Value, Variadic};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Registry key of the name of the scenario which is currently loading
+const CURRENT_SCENARIO_KEY: &str = "zenroom_minimal.scenario.current";
+
+/// The name of the scenario which is currently being loaded by the
+/// [`ScenarioLoader`], if any
+pub(crate) fn current_scenario(ctx: Context) -> LuaResult<Option<String>> {
+    ctx.named_registry_value(CURRENT_SCENARIO_KEY)
+}
 
 /// A trait to define how to load Lua code from an identifier
 /// to be used by the [`ScenarioLoader`]
 pub trait ScenarioLinker {
     /// Define a method for converting an identifier to Lua source
     fn read_scenario(&self, scenario: &str) -> Result<String>;
+
+    /// Read the detached signature of the scenario source, which is checked
+    /// by the [`VerifyingScenarioLinker`](crate::VerifyingScenarioLinker).
+    /// Defaults to no signature
+    fn read_signature(&self, _scenario: &str) -> Result<Option<Octet>> {
+        Ok(None)
+    }
+
+    /// Read the source of a scenario together with its signature. Linkers
+    /// which search for a scenario override it to read the signature next
+    /// to the scenario they found. Defaults to
+    /// [`read_scenario`](ScenarioLinker::read_scenario) and
+    /// [`read_signature`](ScenarioLinker::read_signature)
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        Ok((
+            self.read_scenario(scenario)?,
+            self.read_signature(scenario)?,
+        ))
+    }
+
+    /// Whether the scenario changed since it was last read. A loaded
+    /// scenario which changed is read and run again by the
+    /// [`ScenarioLoader`] the next time it is requested. Defaults to never
+    fn is_modified(&self, _scenario: &str) -> bool {
+        false
+    }
+
+    /// Report that a changed scenario could not be run again. The scenario
+    /// as it was loaded before stays in use. Defaults to doing nothing
+    fn reload_failed(&self, _scenario: &str, _err: &Error) {}
 }
 
-/// [`ScenarioLinker`] which loads Lua source from a File relative to
-/// `cwd`. For example when given `"scenario"` the [`FileScenarioLinker`]
-/// tries to load a file with the name `./zencode_senario.lua`
-#[derive(Clone)]
-pub struct FileScenarioLinker(PathBuf);
+impl<L: ScenarioLinker + ?Sized> ScenarioLinker for Box<L> {
+    fn read_scenario(&self, scenario: &str) -> Result<String> {
+        (**self).read_scenario(scenario)
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        (**self).read_signature(scenario)
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        (**self).read_signed_scenario(scenario)
+    }
+
+    fn is_modified(&self, scenario: &str) -> bool {
+        (**self).is_modified(scenario)
+    }
+
+    fn reload_failed(&self, scenario: &str, err: &Error) {
+        (**self).reload_failed(scenario, err)
+    }
+}
+
+/// Decode a detached signature, which is stored as url safe base64 like
+/// the result of `octet:base64()`
+pub(crate) fn decode_signature(signature: &str) -> Result<Octet> {
+    Ok(Octet::new(base64::decode_config(
+        signature.trim(),
+        base64::URL_SAFE_NO_PAD,
+    )?))
+}
+
+/// [`ScenarioLinker`] which loads Lua source from files in a list of
+/// search directories. For example when given `"scenario"` the
+/// [`FileScenarioLinker`] created with `"."` tries to load a file with the
+/// name `./zencode_scenario.lua`.
+///
+/// The file names are built from patterns, in which `{}` is replaced with
+/// the scenario name. Every pattern is tried in every directory, in the
+/// order in which the directories were added. Scenario names which are
+/// empty or contain path separators or `..` are rejected with
+/// [`Error::InvalidScenarioName`]. The signature of a scenario is read from
+/// the file next to it with `.sig` appended to the name, for example
+/// `zencode_scenario.lua.sig`.
+///
+/// ```
+/// # use zenroom_minimal::FileScenarioLinker;
+/// # fn main() -> zenroom_minimal::Result<()> {
+/// let linker = FileScenarioLinker::new("scenarios")
+///     .path("/usr/share/zencode")
+///     .patterns(&["{}.lua", "{}/scenario.lua"])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FileScenarioLinker {
+    paths: Vec<PathBuf>,
+    patterns: Vec<String>,
+}
 
 impl FileScenarioLinker {
-    /// Create a new [`FileScenarioLinker`] relative to `path`
+    /// Create a new [`FileScenarioLinker`] relative to `path`, which loads
+    /// files named `zencode_{}.lua`
     pub fn new<T: AsRef<Path>>(path: T) -> Self {
-        // TODO vector of paths?
-        FileScenarioLinker(path.as_ref().to_path_buf())
+        FileScenarioLinker {
+            paths: vec![path.as_ref().to_path_buf()],
+            patterns: vec!["zencode_{}.lua".to_string()],
+        }
+    }
+
+    /// Add a directory which is searched after the previous ones
+    pub fn path<T: AsRef<Path>>(mut self, path: T) -> Self {
+        self.paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the patterns of the file names, which are tried in order in
+    /// each directory. A pattern may contain directories, like
+    /// `"{}/scenario.lua"`.
+    ///
+    /// Fails with [`Error::Config`] if a pattern does not contain `{}`
+    pub fn patterns<S: AsRef<str>>(mut self, patterns: &[S]) -> Result<Self> {
+        self.patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let pattern = pattern.as_ref();
+                if pattern.contains("{}") {
+                    Ok(pattern.to_string())
+                } else {
+                    Err(Error::Config(format!(
+                        "scenario file pattern '{}' does not contain '{{}}'",
+                        pattern
+                    )))
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// The files which are tried for a scenario, in order
+    pub(crate) fn candidates(&self, scenario: &str) -> Vec<PathBuf> {
+        self.paths
+            .iter()
+            .flat_map(|path| {
+                self.patterns
+                    .iter()
+                    .map(move |pattern| path.join(pattern.replace("{}", scenario)))
+            })
+            .collect()
+    }
+}
+
+/// Whether a scenario name can be used as part of a file name without
+/// leaving the search directories
+fn is_valid_name(scenario: &str) -> bool {
+    !scenario.is_empty()
+        && !scenario.contains("..")
+        && !scenario.contains('\0')
+        && !scenario.chars().any(std::path::is_separator)
+}
+
+impl FileScenarioLinker {
+    /// The first file of a scenario which exists
+    fn find(&self, scenario: &str) -> Result<PathBuf> {
+        if !is_valid_name(scenario) {
+            return Err(Error::InvalidScenarioName(scenario.to_string()));
+        }
+        let candidates = self.candidates(scenario);
+        for candidate in candidates.iter() {
+            match fs::metadata(candidate) {
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                res => return res.map(|_| candidate.clone()).map_err(Error::from),
+            }
+        }
+        Err(Error::ScenarioNotFound(
+            scenario.to_string(),
+            candidates
+                .iter()
+                .map(|candidate| candidate.display().to_string())
+                .collect(),
+        ))
     }
 }
 
 impl ScenarioLinker for FileScenarioLinker {
     fn read_scenario(&self, scenario: &str) -> Result<String> {
-        // TODO prefix as option
-        read_file(self.0.join(format!("zencode_{}.lua", scenario)))
+        read_file(self.find(scenario)?)
+    }
+
+    fn read_signature(&self, scenario: &str) -> Result<Option<Octet>> {
+        read_signature_file(self.find(scenario)?)
+    }
+
+    fn read_signed_scenario(&self, scenario: &str) -> Result<(String, Option<Octet>)> {
+        let path = self.find(scenario)?;
+        Ok((read_file(&path)?, read_signature_file(path)?))
+    }
+}
+
+/// Read the signature file next to the scenario file `path`
+fn read_signature_file(path: PathBuf) -> Result<Option<Octet>> {
+    let mut signature = path.into_os_string();
+    signature.push(".sig");
+    match read_file(signature) {
+        Ok(signature) => Ok(Some(decode_signature(&signature)?)),
+        Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
     }
 }
 
@@ -43,9 +235,114 @@ impl Default for FileScenarioLinker {
 /// Lua source returned, runs the source in a global scope.
 /// This allows the user to define custom environments in a sandboxed
 /// way. Similar to a `.bashrc` for a Lua VM.
+///
+/// `load_scenario` takes one or more scenario names. A scenario declares
+/// the scenarios it depends on by calling `load_scenario` itself, usually
+/// at the top of its source. Each scenario is run only once per Lua VM, so
+/// later requests for an already loaded scenario do nothing. A scenario
+/// which requests a scenario that is still loading fails with
+/// [`Error::ScenarioCycle`].
+///
+/// Instead of a name, a table `{name = ..., version = ..., sha256 = ...}`
+/// pins the scenario to a version or to the hex encoded SHA-256 hash of its
+/// source. A scenario declares its version with a `-- version: 1.2` comment
+/// before the first line of code. A scenario which does not match is not
+/// run and fails with [`Error::ScenarioMismatch`], which also applies to
+/// scenarios that were already loaded.
+///
+/// If the linker reports that a loaded scenario or one of its dependencies
+/// was modified, like the
+/// [`WatchingScenarioLinker`](crate::WatchingScenarioLinker) does, the
+/// scenario is run again when it is requested next. If that fails, the
+/// error is passed to [`ScenarioLinker::reload_failed`] and the scenario as
+/// it was loaded before stays in use.
+///
+/// ```lua
+/// -- version: 1.0
+/// load_scenario("hash", {name = "encoding", version = "2.1"})
+///
+/// Given("that I have a signed ''", function(name) ... end)
+/// ```
 #[derive(Clone)]
 pub struct ScenarioLoader<L: ScenarioLinker>(L);
 
+/// The scenarios loaded into a Lua VM by a [`ScenarioLoader`]
+#[derive(Default)]
+struct LoadState {
+    loaded: HashMap<String, Loaded>,
+    /// The scenarios which are currently loading, the innermost last, with
+    /// the scenarios they requested so far
+    loading: Vec<(String, Vec<(String, Pin)>)>,
+}
+
+/// The version and hash a scenario is requested with
+#[derive(Clone, Default)]
+struct Pin {
+    version: Option<String>,
+    sha256: Option<String>,
+}
+
+/// The declared version and the hash of the source of a scenario, and the
+/// scenarios it requested while it ran
+struct Loaded {
+    version: Option<String>,
+    sha256: String,
+    dependencies: Vec<(String, Pin)>,
+}
+
+impl Loaded {
+    fn new(source: &str) -> Self {
+        let version = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take_while(|line| line.starts_with("--"))
+            .filter_map(|line| {
+                let comment = line.trim_start_matches('-').trim();
+                if comment.starts_with("version:") {
+                    Some(comment["version:".len()..].trim().to_string())
+                } else {
+                    None
+                }
+            })
+            .next();
+        Loaded {
+            version,
+            sha256: sha256_hex(source.as_bytes()),
+            dependencies: Vec::new(),
+        }
+    }
+
+    fn check(&self, name: &str, pin: &Pin) -> Result<()> {
+        if let Some(ref expected) = pin.version {
+            match self.version {
+                Some(ref version) if version == expected => {}
+                Some(ref version) => {
+                    return Err(Error::ScenarioMismatch(format!(
+                        "scenario '{}' has version '{}', expecting version '{}'",
+                        name, version, expected
+                    )))
+                }
+                None => {
+                    return Err(Error::ScenarioMismatch(format!(
+                        "scenario '{}' does not declare a version, expecting version '{}'",
+                        name, expected
+                    )))
+                }
+            }
+        }
+        if let Some(ref expected) = pin.sha256 {
+            if !self.sha256.eq_ignore_ascii_case(expected) {
+                return Err(Error::ScenarioMismatch(format!(
+                    "scenario '{}' has sha256 '{}', expecting sha256 '{}'",
+                    name, self.sha256, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<L> ScenarioLoader<L>
 where
     L: ScenarioLinker,
@@ -56,17 +353,149 @@ where
         ScenarioLoader(ld)
     }
 
-    fn load_scenario<'lua>(&self, ctx: Context<'lua>, value: Value<'lua>) -> Result<()> {
-        let name = match value {
-            Value::String(s) => s,
-            _ => {
-                return Err(Error::RuntimeError(
-                    "module name must be a string".to_string(),
-                ))
+    /// Box the [`ScenarioLinker`] to erase its type
+    pub(crate) fn boxed(self) -> ScenarioLoader<Box<dyn ScenarioLinker + Send + Sync>>
+    where
+        L: 'static + Send + Sync,
+    {
+        ScenarioLoader(Box::new(self.0))
+    }
+
+    fn load_scenarios<'lua>(
+        &self,
+        ctx: Context<'lua>,
+        state: &Mutex<LoadState>,
+        values: Variadic<Value<'lua>>,
+    ) -> Result<()> {
+        for value in values {
+            let (name, pin) = match value {
+                Value::String(name) => (name.to_str()?.to_string(), Pin::default()),
+                Value::Table(table) => (
+                    table.get::<_, String>("name")?,
+                    Pin {
+                        version: table.get("version")?,
+                        sha256: table.get("sha256")?,
+                    },
+                ),
+                _ => {
+                    return Err(Error::Lua(LuaError::RuntimeError(
+                        "scenario name must be a string".to_string(),
+                    )))
+                }
+            };
+            self.load_scenario(ctx, state, &name, &pin)?;
+        }
+        Ok(())
+    }
+
+    fn load_scenario(
+        &self,
+        ctx: Context,
+        state: &Mutex<LoadState>,
+        name: &str,
+        pin: &Pin,
+    ) -> Result<()> {
+        if let Some((_, dependencies)) = state.lock().unwrap().loading.last_mut() {
+            dependencies.push((name.to_string(), pin.clone()));
+        }
+        self.require(ctx, state, name, pin)
+    }
+
+    /// Load a scenario, or run it again if it or one of its dependencies
+    /// was modified since it was loaded
+    fn require(&self, ctx: Context, state: &Mutex<LoadState>, name: &str, pin: &Pin) -> Result<()> {
+        let dependencies = {
+            let state = state.lock().unwrap();
+            if let Some(pos) = state
+                .loading
+                .iter()
+                .position(|(loading, _)| loading == name)
+            {
+                let mut chain: Vec<_> = state.loading[pos..]
+                    .iter()
+                    .map(|(loading, _)| loading.clone())
+                    .collect();
+                chain.push(name.to_string());
+                return Err(Error::ScenarioCycle(chain));
+            }
+            state
+                .loaded
+                .get(name)
+                .map(|loaded| loaded.dependencies.clone())
+        };
+        let dependencies = match dependencies {
+            Some(dependencies) => dependencies,
+            None => {
+                let loaded = self.enter(ctx, state, name, pin)?;
+                state
+                    .lock()
+                    .unwrap()
+                    .loaded
+                    .insert(name.to_string(), loaded);
+                return Ok(());
             }
         };
-        let scenario = self.0.read_scenario(name.to_str()?)?;
-        ctx.load(&scenario).exec()
+        // the scenario counts as loading while its dependencies are
+        // checked, so that cycles introduced by a reload are found
+        state
+            .lock()
+            .unwrap()
+            .loading
+            .push((name.to_string(), Vec::new()));
+        let res = dependencies
+            .iter()
+            .try_for_each(|(dependency, pin)| self.require(ctx, state, dependency, pin));
+        state.lock().unwrap().loading.pop();
+        res?;
+        if self.0.is_modified(name) {
+            match self.enter(ctx, state, name, pin) {
+                Ok(loaded) => {
+                    state
+                        .lock()
+                        .unwrap()
+                        .loaded
+                        .insert(name.to_string(), loaded);
+                }
+                Err(err) => self.0.reload_failed(name, &err),
+            }
+        }
+        state.lock().unwrap().loaded[name].check(name, pin)
+    }
+
+    /// Run a scenario, collecting the scenarios it requests
+    fn enter(
+        &self,
+        ctx: Context,
+        state: &Mutex<LoadState>,
+        name: &str,
+        pin: &Pin,
+    ) -> Result<Loaded> {
+        state
+            .lock()
+            .unwrap()
+            .loading
+            .push((name.to_string(), Vec::new()));
+        // the lock is not held while the scenario runs, as it may load
+        // its dependencies
+        let res = self.run_scenario(ctx, name, pin);
+        let (_, dependencies) = state.lock().unwrap().loading.pop().unwrap();
+        Ok(Loaded {
+            dependencies,
+            ..res?
+        })
+    }
+
+    /// Read and run a scenario if it matches the pin
+    fn run_scenario(&self, ctx: Context, name: &str, pin: &Pin) -> Result<Loaded> {
+        let scenario = self.0.read_scenario(name)?;
+        let loaded = Loaded::new(&scenario);
+        loaded.check(name, pin)?;
+        let previous = current_scenario(ctx)?;
+        ctx.set_named_registry_value(CURRENT_SCENARIO_KEY, name)?;
+        let res = ctx.load(&scenario).set_name(name)?.exec();
+        ctx.set_named_registry_value(CURRENT_SCENARIO_KEY, previous)?;
+        res?;
+        Ok(loaded)
     }
 }
 
@@ -82,8 +511,10 @@ where
 {
     const IDENTIFIER: &'static str = "load_scenario";
 
-    fn build_module<'lua>(self, ctx: Context<'lua>) -> Result<Value<'lua>> {
-        let func = ctx.create_function(move |ctx, val| Ok(self.load_scenario(ctx, val)?))?;
+    fn build_module<'lua>(self, ctx: Context<'lua>) -> LuaResult<Value<'lua>> {
+        let state = Mutex::new(LoadState::default());
+        let func =
+            ctx.create_function(move |ctx, values| Ok(self.load_scenarios(ctx, &state, values)?))?;
         Ok(Value::Function(func))
     }
 }
@@ -96,9 +527,10 @@ impl DefaultModule for ScenarioLoader<FileScenarioLinker> {
 mod tests {
     use super::*;
     use rand::{prelude::*, thread_rng};
-    use rlua::{Lua, Result};
+    use rlua::Lua;
     use std::fs::{remove_file, File};
     use std::io::prelude::*;
+    use std::sync::Arc;
 
     #[derive(Clone, Debug)]
     struct DummyScenarioLinker;
@@ -117,7 +549,7 @@ mod tests {
     }
 
     #[test]
-    fn dummy_load() -> Result<()> {
+    fn dummy_load() -> LuaResult<()> {
         let lua = Lua::new();
         let loader = ScenarioLoader::new(DummyScenarioLinker);
         let scenario = "hello";
@@ -132,8 +564,194 @@ mod tests {
         })
     }
 
+    #[derive(Clone, Debug)]
+    struct StaticScenarioLinker(Vec<(&'static str, &'static str)>);
+
+    impl ScenarioLinker for StaticScenarioLinker {
+        fn read_scenario(&self, scenario: &str) -> Result<String> {
+            self.0
+                .iter()
+                .find(|(name, _)| *name == scenario)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| Error::ScenarioNotFound(scenario.to_string(), Vec::new()))
+        }
+    }
+
+    #[test]
+    fn dependencies() {
+        let lua = Lua::new();
+        let loader = ScenarioLoader::new(StaticScenarioLinker(vec![
+            ("base", "LOADED = (LOADED or '') .. 'base '"),
+            ("a", "load_scenario('base') LOADED = LOADED .. 'a '"),
+            ("b", "load_scenario('base', 'a') LOADED = LOADED .. 'b '"),
+        ]));
+        let res: String = lua
+            .context(|ctx| {
+                ctx.globals()
+                    .set("load_scenario", loader.build_module(ctx)?)?;
+                ctx.load("load_scenario('b', 'a') load_scenario('base') return LOADED")
+                    .eval()
+            })
+            .unwrap();
+        assert_eq!(res, "base a b ");
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let lua = Lua::new();
+        let loader = ScenarioLoader::new(StaticScenarioLinker(vec![
+            ("a", "load_scenario('b')"),
+            ("b", "load_scenario('c')"),
+            ("c", "load_scenario('a')"),
+        ]));
+        let res = lua.context(|ctx| -> Result<()> {
+            ctx.globals()
+                .set("load_scenario", loader.build_module(ctx)?)?;
+            ctx.load("load_scenario('a')").exec()?;
+            Ok(())
+        });
+        match res {
+            Err(Error::ScenarioCycle(chain)) => assert_eq!(chain, vec!["a", "b", "c", "a"]),
+            res => panic!("expected scenario cycle, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn pinned_scenarios() {
+        let source = "-- the scenario\n-- version: 1.2\nLOADS = (LOADS or 0) + 1";
+        let sha256 = sha256_hex(source.as_bytes());
+        let lua = Lua::new();
+        let loader = ScenarioLoader::new(StaticScenarioLinker(vec![("pinned", source)]));
+        lua.context(|ctx| -> Result<()> {
+            ctx.globals()
+                .set("load_scenario", loader.build_module(ctx)?)?;
+            ctx.globals().set("sha256", sha256.to_uppercase())?;
+            ctx.load("load_scenario({name = 'pinned', version = '1.2'})")
+                .exec()?;
+            ctx.load("load_scenario({name = 'pinned', sha256 = sha256})")
+                .exec()?;
+            Ok(())
+        })
+        .unwrap();
+        let mismatches = [
+            "load_scenario({name = 'pinned', version = '1.3'})",
+            "load_scenario({name = 'pinned', sha256 = '00'})",
+        ];
+        for script in mismatches.iter() {
+            match lua.context(|ctx| ctx.load(script).exec()) {
+                Err(err) => match Error::from(err) {
+                    Error::ScenarioMismatch(_) => {}
+                    err => panic!("expected scenario mismatch, got {:?}", err),
+                },
+                Ok(()) => panic!("expected scenario mismatch for {}", script),
+            }
+        }
+        let loads: i64 = lua.context(|ctx| ctx.globals().get("LOADS")).unwrap();
+        assert_eq!(loads, 1);
+    }
+
+    /// Linker whose scenarios can be changed after they were loaded
+    #[derive(Clone, Default)]
+    struct ReloadingScenarioLinker(Arc<Mutex<Reloading>>);
+
+    #[derive(Default)]
+    struct Reloading {
+        scenarios: HashMap<String, String>,
+        modified: Vec<String>,
+        failed: Vec<(String, String)>,
+    }
+
+    impl ReloadingScenarioLinker {
+        fn set(&self, name: &str, source: &str) {
+            let mut reloading = self.0.lock().unwrap();
+            reloading
+                .scenarios
+                .insert(name.to_string(), source.to_string());
+            reloading.modified.push(name.to_string());
+        }
+    }
+
+    impl ScenarioLinker for ReloadingScenarioLinker {
+        fn read_scenario(&self, scenario: &str) -> Result<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .scenarios
+                .get(scenario)
+                .cloned()
+                .ok_or_else(|| Error::ScenarioNotFound(scenario.to_string(), Vec::new()))
+        }
+
+        fn is_modified(&self, scenario: &str) -> bool {
+            let mut reloading = self.0.lock().unwrap();
+            let before = reloading.modified.len();
+            reloading.modified.retain(|name| name != scenario);
+            reloading.modified.len() != before
+        }
+
+        fn reload_failed(&self, scenario: &str, err: &Error) {
+            self.0
+                .lock()
+                .unwrap()
+                .failed
+                .push((scenario.to_string(), err.to_string()));
+        }
+    }
+
     #[test]
-    fn file_scenario_load() -> Result<()> {
+    fn reload_modified_scenarios() {
+        let linker = ReloadingScenarioLinker::default();
+        linker.set("base", "LOADED = (LOADED or '') .. 'base1 '");
+        linker.set("a", "load_scenario('base') LOADED = LOADED .. 'a '");
+        linker.0.lock().unwrap().modified.clear();
+        let lua = Lua::new();
+        let loader = ScenarioLoader::new(linker.clone());
+        let res: String = lua
+            .context(|ctx| -> Result<String> {
+                ctx.globals()
+                    .set("load_scenario", loader.build_module(ctx)?)?;
+                ctx.load("load_scenario('a')").exec()?;
+                // a modified dependency runs again, the dependent does not
+                linker.set("base", "LOADED = LOADED .. 'base2 '");
+                ctx.load("load_scenario('a')").exec()?;
+                // failing reloads keep the scenario as it was
+                linker.set("a", "load_scenario('missing')");
+                ctx.load("load_scenario('a')").exec()?;
+                linker.set("base", "load_scenario('a')");
+                ctx.load("load_scenario('a')").exec()?;
+                Ok(ctx.globals().get("LOADED")?)
+            })
+            .unwrap();
+        assert_eq!(res, "base1 a base2 ");
+        let failed = &linker.0.lock().unwrap().failed;
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].0, "a");
+        assert!(failed[0].1.contains("'missing' could not be found"));
+        assert_eq!(failed[1].0, "base");
+        assert!(failed[1].1.contains("a -> base -> a"));
+    }
+
+    #[test]
+    fn unpinned_mismatch_is_not_run() {
+        let lua = Lua::new();
+        let loader = ScenarioLoader::new(StaticScenarioLinker(vec![("plain", "LOADED = true")]));
+        let res = lua.context(|ctx| -> Result<Option<bool>> {
+            ctx.globals()
+                .set("load_scenario", loader.build_module(ctx)?)?;
+            let res = ctx
+                .load("load_scenario({name = 'plain', version = '1.0'})")
+                .exec();
+            match res.map_err(Error::from) {
+                Err(Error::ScenarioMismatch(_)) => {}
+                res => panic!("expected scenario mismatch, got {:?}", res),
+            }
+            Ok(ctx.globals().get("LOADED")?)
+        });
+        assert_eq!(res.unwrap(), None);
+    }
+
+    #[test]
+    fn file_scenario_load() -> LuaResult<()> {
         // TODO make this windows compatible
         let lua = Lua::new();
         let linker = FileScenarioLinker::new("/tmp");
@@ -158,4 +776,94 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn file_scenario_not_found() {
+        let linker = FileScenarioLinker::new("/tmp");
+        match linker.read_scenario(&random_scenario(10)) {
+            Err(Error::ScenarioNotFound(_, ref tried)) => assert_eq!(tried.len(), 1),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn search_paths() {
+        let scenario = random_scenario(10);
+        let dir = std::env::temp_dir().join(format!("zencode_{}", random_scenario(10)));
+        std::fs::create_dir_all(dir.join(&scenario)).unwrap();
+        let filename = dir.join(&scenario).join("scenario.lua");
+        File::create(&filename)
+            .and_then(|mut file| file.write_all(b"return 1"))
+            .unwrap();
+
+        let linker = FileScenarioLinker::new("/nonexistent")
+            .path(&dir)
+            .patterns(&["zencode_{}.lua", "{}/scenario.lua"])
+            .unwrap();
+        assert_eq!(linker.read_scenario(&scenario).unwrap(), "return 1");
+        match linker.read_scenario("missing") {
+            Err(Error::ScenarioNotFound(ref name, ref tried)) => {
+                assert_eq!(name, "missing");
+                assert_eq!(
+                    tried,
+                    &vec![
+                        "/nonexistent/zencode_missing.lua".to_string(),
+                        "/nonexistent/missing/scenario.lua".to_string(),
+                        dir.join("zencode_missing.lua").display().to_string(),
+                        dir.join("missing/scenario.lua").display().to_string(),
+                    ]
+                );
+            }
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        match FileScenarioLinker::new("/tmp").patterns(&["{}.lua", "scenario.lua"]) {
+            Err(Error::Config(ref msg)) => assert!(msg.contains("scenario.lua")),
+            res => panic!("expected invalid configuration, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn invalid_names() {
+        let linker = FileScenarioLinker::new("/tmp");
+        for name in &["", "..", "../etc/passwd", "a/b", "a..b"] {
+            match linker.read_scenario(name) {
+                Err(Error::InvalidScenarioName(_)) => (),
+                res => panic!("expected invalid scenario name, got {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn file_signature() {
+        let dir = std::env::temp_dir().join(format!("zencode_{}", random_scenario(10)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: &[(&str, &[u8])] = &[
+            ("zencode_signed.lua", b"return 1"),
+            ("zencode_signed.lua.sig", b"AAEC\n"),
+            ("zencode_unsigned.lua", b"return 2"),
+        ];
+        for (name, content) in files {
+            File::create(dir.join(name))
+                .and_then(|mut file| file.write_all(content))
+                .unwrap();
+        }
+
+        let linker = FileScenarioLinker::new(&dir);
+        let signature = linker.read_signature("signed").unwrap().unwrap();
+        assert_eq!(&signature[..], &[0, 1, 2]);
+        let (source, signature) = linker.read_signed_scenario("signed").unwrap();
+        assert_eq!(source, "return 1");
+        assert_eq!(&signature.unwrap()[..], &[0, 1, 2]);
+        assert!(linker.read_signature("unsigned").unwrap().is_none());
+        match linker.read_signature("missing") {
+            Err(Error::ScenarioNotFound(..)) => (),
+            res => panic!("expected scenario not found, got {:?}", res),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,831 @@
+mod param;
+mod parser;
+mod program;
+mod scope;
+mod statement;
+
+pub use self::scope::Scope;
+pub use self::statement::Phase;
+
+use self::parser::{Kind, Line};
+use self::program::{Builder, Node, Runner, Step, TRACEBACK};
+use self::statement::{normalize, rust_handler, Handler, RustHandler, Statement, Statements};
+use super::{current_scenario, DefaultModule, FileScenarioLinker, Module, ScenarioLoader};
+use crate::{Error, Result, ZencodeError};
+use rlua::{AnyUserData, Context, FromLuaMulti, Function, Table, UserData, Value};
+use std::sync::{Arc, Mutex};
+
+/// Registry key of the [`State`] of the [`Zencode`] module in a Lua VM
+const STATE_KEY: &str = "zenroom_minimal.zencode";
+
+/// The registered statements and the parsed script of a Lua VM
+#[derive(Default)]
+struct State {
+    verbosity: u8,
+    statements: Statements,
+    program: Vec<Node>,
+}
+
+struct StateData(Arc<Mutex<State>>);
+
+impl UserData for StateData {}
+
+/// A statement implemented in Rust, to be registered when the module is built
+#[derive(Clone)]
+struct RustStatement {
+    phase: Phase,
+    pattern: String,
+    handler: RustHandler,
+}
+
+/// Exposes a `ZEN` module for parsing and running Zencode, compatible with
+/// [zencode-core](https://github.com/riddleandcode/zencode-core).
+///
+/// Building the module defines the global functions `Given`, `When` and
+/// `Then`, which register a statement pattern of the phase with a Lua
+/// function. Every quoted placeholder in a pattern matches a quoted
+/// parameter, which is converted to the type of the placeholder and passed
+/// to the function. Parameters which can not be converted fail the parsing
+/// of the script
+/// * `''` or `'<string>'`: a string
+/// * `'<number>'`: an integer or a float
+/// * `'<octet>'`: an [`Octet`](crate::Octet), decoded from url safe base64.
+///   `'<octet:hex>'` and `'<octet:string>'` select another encoding
+/// * `'<data>'` and `'<key>'`: the name of a value in `ACK` or `IN` and in
+///   `KEYS`, which passes the value. A missing value fails the statement
+///   when it is run
+///
+/// Besides statements, a script can contain blocks, which can be nested
+/// * `If <statement>` ... `EndIf`: runs the statement of the current phase
+///   as condition, and the block if it returned a value other than `nil`
+///   or `false`
+/// * `Foreach 'item' in 'list'` ... `EndForeach`: runs the block for each
+///   value of the array `list` in `ACK` or `IN`, with `ACK[item]` set to
+///   the value
+///
+/// The `ZEN` module has the methods
+/// * `ZEN:begin(verbosity)`: Sets the verbosity and clears `ZEN_traceback`.
+///   A verbosity of `0` disables the traceback
+/// * `ZEN:reset()`: Resets the parsed script
+/// * `ZEN:parse(script)`: Parses the statements of a script. Each
+///   `Scenario 'name'` line loads the scenario with the global
+///   `load_scenario`, which skips scenarios that are already loaded.
+///   `Scenario 'name' version '1.2' sha256 '<hex>'` pins the scenario to a
+///   version and the hash of its source, which fails the parsing if the
+///   scenario does not match
+/// * `ZEN:run(data, keys)`: Runs the parsed statements with `IN` set to
+///   `data`, `KEYS` set to `keys` and `ACK` and `OUT` set to empty tables,
+///   returning `OUT`. Each statement run is appended to `ZEN_traceback`
+///
+/// Statements can also be implemented in Rust, with typed parameters and
+/// access to `ACK`, `IN`, `KEYS` and `OUT` through a [`Scope`]:
+///
+/// ```
+/// # use zenroom_minimal::{prelude::*, Phase, Scope, Zencode};
+/// let zencode = Zencode::new().statement(
+///     Phase::When,
+///     "I add '<number>' to '<data>'",
+///     |scope: &mut Scope, (a, b): (i64, i64)| {
+///         scope.ack.set("sum", a + b)?;
+///         Ok(())
+///     },
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct Zencode {
+    statements: Vec<RustStatement>,
+}
+
+impl Zencode {
+    /// Create a new [`Zencode`] module without statements implemented in Rust
+    pub fn new() -> Self {
+        Zencode::default()
+    }
+
+    /// Register a statement implemented in Rust when the module is built.
+    /// The quoted parameters of the statement are converted to `A`, for
+    /// example to `(String, i64)`
+    pub fn statement<A, F>(mut self, phase: Phase, pattern: &str, handler: F) -> Self
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<()>,
+    {
+        self.statements.push(RustStatement {
+            phase,
+            pattern: pattern.to_string(),
+            handler: Zencode::rust_handler(handler),
+        });
+        self
+    }
+
+    /// Register a statement implemented in Rust in a Lua VM in which the
+    /// module was built, replacing a statement with the same pattern
+    pub fn register_statement<A, F>(
+        ctx: Context,
+        phase: Phase,
+        pattern: &str,
+        handler: F,
+    ) -> Result<()>
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<()>,
+    {
+        Zencode::register_rust(ctx, phase, pattern, Zencode::rust_handler(handler))
+    }
+
+    /// Register a condition implemented in Rust when the module is built.
+    /// A condition is a statement used in an `If` block, which runs the
+    /// block if `handler` returns `true`
+    pub fn condition<A, F>(mut self, phase: Phase, pattern: &str, handler: F) -> Self
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<bool>,
+    {
+        self.statements.push(RustStatement {
+            phase,
+            pattern: pattern.to_string(),
+            handler: Zencode::rust_condition(handler),
+        });
+        self
+    }
+
+    /// Register a condition implemented in Rust in a Lua VM in which the
+    /// module was built, replacing a statement with the same pattern
+    pub fn register_condition<A, F>(
+        ctx: Context,
+        phase: Phase,
+        pattern: &str,
+        handler: F,
+    ) -> Result<()>
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<bool>,
+    {
+        Zencode::register_rust(ctx, phase, pattern, Zencode::rust_condition(handler))
+    }
+
+    fn register_rust(
+        ctx: Context,
+        phase: Phase,
+        pattern: &str,
+        handler: RustHandler,
+    ) -> Result<()> {
+        let state = match ctx.named_registry_value::<_, Option<AnyUserData>>(STATE_KEY)? {
+            Some(data) => data.borrow::<StateData>()?.0.clone(),
+            None => {
+                return Err(Error::Lua(rlua::Error::RuntimeError(
+                    "zencode module is not loaded".to_string(),
+                )))
+            }
+        };
+        let statement = Statement::new(phase, pattern.to_string(), None, Handler::Rust(handler))
+            .map_err(Error::ZencodeParse)?;
+        state.lock().unwrap().statements.register(statement);
+        Ok(())
+    }
+
+    fn rust_handler<A, F>(handler: F) -> RustHandler
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<()>,
+    {
+        rust_handler(move |ctx, args| {
+            let args = A::from_lua_multi(args, ctx)?;
+            let mut scope = Scope::load(ctx)?;
+            handler(&mut scope, args)?;
+            scope.store()?;
+            Ok(Value::Nil)
+        })
+    }
+
+    fn rust_condition<A, F>(handler: F) -> RustHandler
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<bool>,
+    {
+        rust_handler(move |ctx, args| {
+            let args = A::from_lua_multi(args, ctx)?;
+            let mut scope = Scope::load(ctx)?;
+            let res = handler(&mut scope, args)?;
+            scope.store()?;
+            Ok(Value::Boolean(res))
+        })
+    }
+
+    fn register_lua(
+        ctx: Context,
+        state: &Mutex<State>,
+        phase: Phase,
+        pattern: String,
+        handler: Function,
+    ) -> Result<()> {
+        let statement = Statement::new(
+            phase,
+            pattern,
+            current_scenario(ctx)?,
+            Handler::Lua(Arc::new(ctx.create_registry_value(handler)?)),
+        )
+        .map_err(Error::ZencodeParse)?;
+        state.lock().unwrap().statements.register(statement);
+        Ok(())
+    }
+
+    fn begin(ctx: Context, state: &Mutex<State>, verbosity: u8) -> Result<()> {
+        state.lock().unwrap().verbosity = verbosity;
+        Ok(ctx.globals().set(TRACEBACK, "")?)
+    }
+
+    fn reset(ctx: Context, state: &Mutex<State>) -> Result<()> {
+        state.lock().unwrap().program.clear();
+        Ok(ctx.globals().set(TRACEBACK, "")?)
+    }
+
+    fn parse(ctx: Context, state: &Mutex<State>, script: &str) -> Result<()> {
+        let lines = parser::lines(script).map_err(Error::ZencodeParse)?;
+        let mut program = Builder::default();
+        let mut scenario = None;
+        for line in &lines {
+            match line.kind {
+                Kind::Scenario {
+                    ref name,
+                    ref version,
+                    ref sha256,
+                } => {
+                    // the lock is not held, as the scenario registers statements
+                    let load = ctx
+                        .globals()
+                        .get::<_, Option<Function>>(
+                            ScenarioLoader::<FileScenarioLinker>::GLOBAL_VAR,
+                        )?
+                        .ok_or_else(|| {
+                            Error::ZencodeParse(line.error("scenarios can not be loaded"))
+                        })?;
+                    let request = ctx.create_table()?;
+                    request.set("name", name.as_str())?;
+                    request.set("version", version.clone())?;
+                    request.set("sha256", sha256.clone())?;
+                    load.call::<_, ()>(request)?;
+                    scenario = Some(name.clone());
+                }
+                Kind::Statement(phase, text) => {
+                    let step = Zencode::step(state, line, phase, text, &scenario)?;
+                    program.push(Node::Step(step));
+                }
+                Kind::If(phase, text) => {
+                    let condition = Zencode::step(state, line, phase, text, &scenario)?;
+                    program.push(Node::If {
+                        condition,
+                        body: Vec::new(),
+                    });
+                }
+                Kind::Foreach { ref item, ref list } => program.push(Node::Foreach {
+                    line: line.number,
+                    source: line.source.to_string(),
+                    scenario: scenario.clone(),
+                    item: item.clone(),
+                    list: list.clone(),
+                    body: Vec::new(),
+                }),
+                Kind::EndIf | Kind::EndForeach => {
+                    program.close(line).map_err(Error::ZencodeParse)?
+                }
+            }
+        }
+        let program = program.finish().map_err(Error::ZencodeParse)?;
+        state.lock().unwrap().program.extend(program);
+        Ok(())
+    }
+
+    /// Find the statement matching a line and convert its parameters
+    fn step(
+        state: &Mutex<State>,
+        line: &Line,
+        phase: Phase,
+        text: &str,
+        scenario: &Option<String>,
+    ) -> Result<Step> {
+        let normalized = normalize(text);
+        let state = state.lock().unwrap();
+        let statement = match state.statements.find(phase, &normalized) {
+            Some(statement) => statement.clone(),
+            None => {
+                return Err(Error::ZencodeParse(ZencodeError {
+                    scenario: scenario.clone(),
+                    suggestion: state.statements.suggest(phase, &normalized),
+                    ..line.error("no matching statement is registered")
+                }))
+            }
+        };
+        let args = parser::arguments(text)
+            .iter()
+            .zip(&statement.params)
+            .enumerate()
+            .map(|(index, (text, param))| {
+                param.parse(text).map_err(|message| {
+                    Error::ZencodeParse(ZencodeError {
+                        scenario: scenario.clone(),
+                        ..line.error(format!("parameter {}: {}", index + 1, message))
+                    })
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Step {
+            line: line.number,
+            source: line.source.to_string(),
+            statement,
+            args,
+        })
+    }
+
+    fn run<'lua>(
+        ctx: Context<'lua>,
+        state: &Mutex<State>,
+        data: Value<'lua>,
+        keys: Value<'lua>,
+    ) -> Result<Value<'lua>> {
+        let (verbosity, program) = {
+            let state = state.lock().unwrap();
+            (state.verbosity, state.program.clone())
+        };
+        let globals = ctx.globals();
+        globals.set("IN", Zencode::table_or_empty(ctx, data)?)?;
+        globals.set("KEYS", Zencode::table_or_empty(ctx, keys)?)?;
+        globals.set("ACK", ctx.create_table()?)?;
+        globals.set("OUT", ctx.create_table()?)?;
+        Runner::new(ctx, verbosity)?.run(&program)?;
+        Ok(globals.get("OUT")?)
+    }
+
+    fn table_or_empty<'lua>(ctx: Context<'lua>, value: Value<'lua>) -> Result<Value<'lua>> {
+        match value {
+            Value::Nil => Ok(Value::Table(ctx.create_table()?)),
+            value => Ok(value),
+        }
+    }
+}
+
+impl Module for Zencode {
+    const IDENTIFIER: &'static str = "zencode";
+
+    fn build_module<'lua>(self, ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        let mut statements = Statements::default();
+        for statement in self.statements {
+            statements.register(
+                Statement::new(
+                    statement.phase,
+                    statement.pattern,
+                    None,
+                    Handler::Rust(statement.handler),
+                )
+                .map_err(Error::ZencodeParse)?,
+            );
+        }
+        let state = Arc::new(Mutex::new(State {
+            verbosity: 1,
+            statements,
+            program: Vec::new(),
+        }));
+        ctx.set_named_registry_value(STATE_KEY, StateData(state.clone()))?;
+        for phase in Phase::ALL.iter().cloned() {
+            let state = state.clone();
+            let register =
+                ctx.create_function(move |ctx, (pattern, handler): (String, Function)| {
+                    Ok(Zencode::register_lua(ctx, &state, phase, pattern, handler)?)
+                })?;
+            ctx.globals().set(phase.keyword(), register)?;
+        }
+
+        let module: Table = ctx.create_table()?;
+        let begin_state = state.clone();
+        module.set(
+            "begin",
+            ctx.create_function(move |ctx, (_, verbosity): (Value, Option<u8>)| {
+                Ok(Zencode::begin(ctx, &begin_state, verbosity.unwrap_or(1))?)
+            })?,
+        )?;
+        let reset_state = state.clone();
+        module.set(
+            "reset",
+            ctx.create_function(move |ctx, _: Value| Ok(Zencode::reset(ctx, &reset_state)?))?,
+        )?;
+        let parse_state = state.clone();
+        module.set(
+            "parse",
+            ctx.create_function(move |ctx, (_, script): (Value, String)| {
+                Ok(Zencode::parse(ctx, &parse_state, &script)?)
+            })?,
+        )?;
+        module.set(
+            "run",
+            ctx.create_function(move |ctx, (_, data, keys): (Value, Value, Value)| {
+                Ok(Zencode::run(ctx, &state, data, keys)?)
+            })?,
+        )?;
+        ctx.globals().set(TRACEBACK, "")?;
+        Ok(Value::Table(module))
+    }
+}
+
+impl DefaultModule for Zencode {
+    const GLOBAL_VAR: &'static str = "ZEN";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlua::{Lua, Result};
+
+    #[test]
+    fn helloworld() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.context(|lua_ctx| {
+            Zencode::import_module(lua_ctx)?;
+            lua_ctx
+                .load(
+                    r#"
+Given("that my name is ''", function(name)
+    ACK.name = name
+end)
+
+Then("say hello", function()
+    OUT = "Hello, " .. ACK.name .. "!"
+end)
+
+Then("print all data", function()
+    -- print(OUT)
+end)
+"#,
+                )
+                .exec()
+        })
+        .and_then(|_| {
+            lua.context(|lua_ctx| {
+                lua_ctx
+                    .load(
+                        r#"
+ZEN:begin(1)
+
+local script = [[
+Given that my name is 'Julian'
+Then say hello
+And print all data
+]]
+
+ZEN:parse(script)
+return ZEN:run({}, {})
+                "#,
+                    )
+                    .eval()
+            })
+        })
+        .and_then(|out: std::string::String| {
+            assert_eq!(out, "Hello, Julian!".to_string());
+            Ok(())
+        })
+    }
+
+    fn zencode(scenario: &str, script: &str) -> crate::Result<Lua> {
+        let lua = Lua::new();
+        lua.context(|ctx| -> crate::Result<()> {
+            Zencode::import_module(ctx)?;
+            ctx.load(scenario).exec()?;
+            let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+            let parse: Function = zen.get("parse")?;
+            parse.call::<_, ()>((zen, script))?;
+            Ok(())
+        })?;
+        Ok(lua)
+    }
+
+    fn run(lua: &Lua) -> crate::Result<Option<String>> {
+        lua.context(|ctx| {
+            let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+            let run: Function = zen.get("run")?;
+            Ok(run.call((zen, Value::Nil, Value::Nil))?)
+        })
+    }
+
+    const GREETING: &str = r#"
+Given("that my name is ''", function(name)
+    ACK.name = name
+end)
+
+Then("say hello", function()
+    OUT = "Hello, " .. ACK.name .. "!"
+end)
+
+Then("fail", function()
+    error("failed on purpose")
+end)
+"#;
+
+    #[test]
+    fn unknown_statement() {
+        let res = zencode(GREETING, "Given that my name is 'Julian'\nThen say helo");
+        match res {
+            Err(Error::ZencodeParse(ref err)) => {
+                assert_eq!(err.line, Some(2));
+                assert_eq!(err.statement, Some("Then say helo".to_string()));
+                assert_eq!(err.phase, Some(Phase::Then));
+                assert_eq!(err.suggestion, Some("say hello".to_string()));
+            }
+            Err(err) => panic!("expected zencode parse error, got {:?}", err),
+            Ok(_) => panic!("expected zencode parse error"),
+        }
+    }
+
+    #[test]
+    fn begin_after_parse() {
+        let lua = zencode(GREETING, "Given that my name is 'Julian'\nThen say hello").unwrap();
+        lua.context(|ctx| -> crate::Result<()> {
+            let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+            let begin: Function = zen.get("begin")?;
+            Ok(begin.call::<_, ()>((zen, 1))?)
+        })
+        .unwrap();
+        assert_eq!(run(&lua).unwrap(), Some("Hello, Julian!".to_string()));
+    }
+
+    #[test]
+    fn failing_statement() {
+        let lua = zencode(GREETING, "Given that my name is 'Julian'\n\nThen fail").unwrap();
+        match run(&lua) {
+            Err(Error::ZencodeRun(ref err)) => {
+                assert_eq!(err.line, Some(3));
+                assert_eq!(err.statement, Some("Then fail".to_string()));
+                assert_eq!(err.phase, Some(Phase::Then));
+                assert!(err.message.contains("failed on purpose"));
+            }
+            res => panic!("expected zencode run error, got {:?}", res),
+        }
+        let traceback: String = lua.context(|ctx| ctx.globals().get(TRACEBACK)).unwrap();
+        assert_eq!(
+            traceback,
+            "    -> Given that my name is 'Julian'\n    -> Then fail\n"
+        );
+    }
+
+    #[test]
+    fn replace_statement() {
+        let scenario = format!(
+            "{}\nThen('say hello', function() OUT = 'Hi, ' .. ACK.name end)",
+            GREETING
+        );
+        let lua = zencode(&scenario, "Given that my name is 'Julian'\nThen say hello").unwrap();
+        assert_eq!(run(&lua).unwrap(), Some("Hi, Julian".to_string()));
+    }
+
+    #[test]
+    fn rust_statements() {
+        let zencode = Zencode::new()
+            .statement(
+                Phase::Given,
+                "that I add '' to ''",
+                |scope: &mut Scope, (a, b): (i64, i64)| {
+                    scope.ack.set("sum", a + b)?;
+                    Ok(())
+                },
+            )
+            .statement(Phase::Then, "print the sum", |scope: &mut Scope, ()| {
+                scope.out = scope.ack.get("sum")?;
+                Ok(())
+            });
+        let lua = Lua::new();
+        lua.context(|ctx| {
+            ctx.globals()
+                .set(Zencode::GLOBAL_VAR, zencode.build_module(ctx)?)?;
+            Zencode::register_statement(ctx, Phase::When, "I double it", |scope: &mut Scope, ()| {
+                let sum: i64 = scope.ack.get("sum")?;
+                scope.ack.set("sum", sum * 2)?;
+                Ok(())
+            })
+        })
+        .unwrap();
+        let script = r#"
+ZEN:parse([[
+Given that I add '1' to '2'
+When I double it
+Then print the sum
+]])
+return ZEN:run()
+"#;
+        let res: i64 = lua.context(|ctx| ctx.load(script).eval()).unwrap();
+        assert_eq!(res, 6);
+    }
+
+    #[test]
+    fn rust_statement_parameters() {
+        let zencode = Zencode::new().statement(
+            Phase::Given,
+            "the number ''",
+            |_: &mut Scope, (_,): (i64,)| Ok(()),
+        );
+        let lua = Lua::new();
+        let res = lua.context(|ctx| -> crate::Result<()> {
+            ctx.globals()
+                .set(Zencode::GLOBAL_VAR, zencode.build_module(ctx)?)?;
+            ctx.load("ZEN:parse(\"Given the number 'one'\") ZEN:run()")
+                .exec()?;
+            Ok(())
+        });
+        match res {
+            Err(Error::ZencodeRun(ref err)) => assert_eq!(err.line, Some(1)),
+            res => panic!("expected zencode run error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rust_statement_error() {
+        let zencode = Zencode::new()
+            .statement(Phase::Given, "nothing", |_: &mut Scope, ()| Ok(()))
+            .statement(Phase::Then, "sign it", |_: &mut Scope, ()| {
+                Err(Error::Crypto("bad key".to_string()))
+            });
+        let lua = Lua::new();
+        let res = lua.context(|ctx| -> crate::Result<()> {
+            ctx.globals()
+                .set(Zencode::GLOBAL_VAR, zencode.build_module(ctx)?)?;
+            ctx.load("ZEN:parse(\"Given nothing\\nThen sign it\") ZEN:run()")
+                .exec()?;
+            Ok(())
+        });
+        match res {
+            Err(Error::ZencodeRun(ref err)) => {
+                assert_eq!(err.line, Some(2));
+                assert_eq!(err.statement, Some("Then sign it".to_string()));
+                assert_eq!(err.phase, Some(Phase::Then));
+                assert!(err.message.contains("bad key"));
+            }
+            res => panic!("expected zencode run error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn typed_parameters() {
+        let lua = zencode(
+            r#"
+Given("that I have '<data>' and '<number>'", function(value, number)
+    ACK.sum = value + number
+end)
+Then("print the sum as '<octet:hex>'", function(octet)
+    OUT.sum = ACK.sum
+    OUT.bytes = octet:string()
+end)
+"#,
+            "Given that I have 'a' and '2.5'\nThen print the sum as '6869'",
+        )
+        .unwrap();
+        let res = lua
+            .context(|ctx| {
+                ctx.load("local out = ZEN:run({a = 1}) return out.sum .. out.bytes")
+                    .eval::<String>()
+            })
+            .unwrap();
+        assert_eq!(res, "3.5hi");
+    }
+
+    #[test]
+    fn parameter_type_errors() {
+        let scenario = r#"
+Given("that I have '<number>'", function(number) end)
+When("I use '<key>'", function(key) end)
+"#;
+        match zencode(scenario, "Given that I have '1'\nGiven that I have 'one'") {
+            Err(Error::ZencodeParse(ref err)) => {
+                assert_eq!(err.line, Some(2));
+                assert_eq!(err.message, "parameter 1: expecting a number, got 'one'");
+            }
+            Err(err) => panic!("expected zencode parse error, got {:?}", err),
+            Ok(_) => panic!("expected zencode parse error"),
+        }
+        let lua = zencode(scenario, "When I use 'secret'").unwrap();
+        match run(&lua) {
+            Err(Error::ZencodeRun(ref err)) => {
+                assert_eq!(err.line, Some(1));
+                assert!(err.message.contains("'secret' is not in KEYS"));
+            }
+            res => panic!("expected zencode run error, got {:?}", res),
+        }
+        match zencode("Given(\"I have '<int>'\", function() end)", "") {
+            Err(Error::ZencodeParse(ref err)) => {
+                assert_eq!(err.message, "unknown parameter type '<int>'")
+            }
+            Err(err) => panic!("expected zencode parse error, got {:?}", err),
+            Ok(_) => panic!("expected zencode parse error"),
+        }
+    }
+
+    const BLOCKS: &str = r#"
+Given("I have nothing", function() end)
+When("'<data>' is more than '<number>'", function(value, limit)
+    return value > limit
+end)
+When("I count '<data>'", function(value)
+    ACK.count = (ACK.count or 0) + 1
+    ACK.total = (ACK.total or 0) + value
+end)
+Then("print the total", function()
+    OUT = ACK.count .. " " .. ACK.total
+end)
+"#;
+
+    #[test]
+    fn blocks() {
+        let lua = zencode(
+            BLOCKS,
+            r#"
+Given I have nothing
+Foreach 'value' in 'values'
+If 'value' is more than '1'
+When I count 'value'
+EndIf
+EndForeach
+If 'limit' is more than '10'
+When I count 'limit'
+EndIf
+Then print the total
+"#,
+        )
+        .unwrap();
+        let res = lua
+            .context(|ctx| {
+                ctx.load("return ZEN:run({values = {1, 2, 3}, limit = 10})")
+                    .eval::<String>()
+            })
+            .unwrap();
+        assert_eq!(res, "2 5");
+        let traceback = lua
+            .context(|ctx| ctx.globals().get::<_, String>(TRACEBACK))
+            .unwrap();
+        assert_eq!(traceback.matches("When I count 'value'").count(), 2);
+        assert!(!traceback.contains("When I count 'limit'"));
+    }
+
+    #[test]
+    fn block_errors() {
+        let errors = [
+            ("Given I have nothing\nIf 'a' is more than '1'", 2),
+            ("Given I have nothing\nEndIf", 2),
+            ("Given I have nothing\nForeach 'a' in 'b'\nEndIf", 3),
+        ];
+        for &(script, line) in errors.iter() {
+            match zencode(BLOCKS, script) {
+                Err(Error::ZencodeParse(ref err)) => assert_eq!(err.line, Some(line)),
+                Err(err) => panic!("expected zencode parse error, got {:?}", err),
+                Ok(_) => panic!("expected zencode parse error for {:?}", script),
+            }
+        }
+        let lua = zencode(
+            BLOCKS,
+            "Given I have nothing\nForeach 'a' in 'missing'\nWhen I count 'a'\nEndForeach",
+        )
+        .unwrap();
+        match run(&lua) {
+            Err(Error::ZencodeRun(ref err)) => {
+                assert_eq!(err.line, Some(2));
+                assert_eq!(err.message, "'missing' is not in ACK or IN");
+            }
+            res => panic!("expected zencode run error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rust_conditions() {
+        let zencode = Zencode::new()
+            .condition(
+                Phase::Given,
+                "'<number>' is even",
+                |_: &mut Scope, (number,): (i64,)| Ok(number % 2 == 0),
+            )
+            .statement(
+                Phase::Given,
+                "say ''",
+                |scope: &mut Scope, (text,): (String,)| {
+                    scope.out = Value::String(scope.context().create_string(&text)?);
+                    Ok(())
+                },
+            );
+        let lua = Lua::new();
+        let script = r#"
+ZEN:parse([[
+Given say 'none'
+If '3' is even
+Given say 'three'
+EndIf
+If '4' is even
+Given say 'four'
+EndIf
+]])
+return ZEN:run()
+"#;
+        let res: String = lua
+            .context(|ctx| -> crate::Result<String> {
+                ctx.globals()
+                    .set(Zencode::GLOBAL_VAR, zencode.build_module(ctx)?)?;
+                Ok(ctx.load(script).eval()?)
+            })
+            .unwrap();
+        assert_eq!(res, "four");
+    }
+}
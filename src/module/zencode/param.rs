@@ -0,0 +1,208 @@
+use crate::Octet;
+use rlua::{Context, Table, Value};
+use std::fmt;
+
+/// The encoding of an octet parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    /// Url safe base64 without padding, like `OCTET.base64`
+    Base64,
+    /// Hexadecimal, in upper or lower case
+    Hex,
+    /// The utf-8 bytes of the text, like `OCTET.string`
+    String,
+}
+
+/// The type of a quoted parameter in a statement pattern.
+///
+/// Placeholders are written as `'<type>'` in a pattern, any other quoted
+/// text (usually `''`) is an untyped string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Param {
+    /// `''` or `'<string>'`: the text as a Lua string
+    String,
+    /// `'<number>'`: an integer or a float
+    Number,
+    /// `'<octet>'` or `'<octet:encoding>'`: an [`Octet`] decoded from
+    /// `base64` (the default), `hex` or `string`
+    Octet(Encoding),
+    /// `'<data>'`: the name of a value in `ACK` or `IN`, passed as the value
+    Data,
+    /// `'<key>'`: the name of a value in `KEYS`, passed as the value
+    Key,
+}
+
+/// A parameter of a parsed statement, converted to its [`Param`] type
+#[derive(Clone, Debug)]
+pub(crate) enum Argument {
+    String(String),
+    Integer(i64),
+    Number(f64),
+    Octet(Octet),
+    Data(String),
+    Key(String),
+}
+
+impl Param {
+    /// Parse a quoted placeholder of a pattern
+    pub fn from_placeholder(placeholder: &str) -> Result<Param, String> {
+        let placeholder = placeholder.trim();
+        if !placeholder.starts_with('<') || !placeholder.ends_with('>') {
+            return Ok(Param::String);
+        }
+        let name = placeholder[1..placeholder.len() - 1].trim();
+        let mut parts = name.splitn(2, ':');
+        let param = match (parts.next().unwrap_or(""), parts.next()) {
+            ("string", None) => Param::String,
+            ("number", None) => Param::Number,
+            ("octet", None) => Param::Octet(Encoding::Base64),
+            ("octet", Some("base64")) => Param::Octet(Encoding::Base64),
+            ("octet", Some("hex")) => Param::Octet(Encoding::Hex),
+            ("octet", Some("string")) => Param::Octet(Encoding::String),
+            ("data", None) => Param::Data,
+            ("key", None) => Param::Key,
+            _ => return Err(format!("unknown parameter type '{}'", placeholder)),
+        };
+        Ok(param)
+    }
+
+    /// Convert the quoted text of a statement to an [`Argument`]
+    pub fn parse(self, text: &str) -> Result<Argument, String> {
+        match self {
+            Param::String => Ok(Argument::String(text.to_string())),
+            Param::Number => {
+                let trimmed = text.trim();
+                if let Ok(integer) = trimmed.parse() {
+                    return Ok(Argument::Integer(integer));
+                }
+                match trimmed.parse::<f64>() {
+                    Ok(number) if number.is_finite() => Ok(Argument::Number(number)),
+                    _ => Err(format!("expecting a number, got '{}'", text)),
+                }
+            }
+            Param::Octet(encoding) => encoding
+                .decode(text)
+                .map(|bytes| Argument::Octet(Octet::new(bytes)))
+                .ok_or_else(|| format!("expecting a {} encoded octet, got '{}'", encoding, text)),
+            Param::Data | Param::Key => {
+                let global = if self == Param::Data {
+                    "ACK or IN"
+                } else {
+                    "KEYS"
+                };
+                if text.is_empty() || text.contains(char::is_whitespace) {
+                    return Err(format!(
+                        "expecting the name of a value in {}, got '{}'",
+                        global, text
+                    ));
+                }
+                Ok(if self == Param::Data {
+                    Argument::Data(text.to_string())
+                } else {
+                    Argument::Key(text.to_string())
+                })
+            }
+        }
+    }
+}
+
+impl Encoding {
+    fn decode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Base64 => base64::decode_config(text, base64::URL_SAFE_NO_PAD).ok(),
+            Encoding::Hex => {
+                if text.len() % 2 != 0 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                (0..text.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+                    .collect()
+            }
+            Encoding::String => Some(text.as_bytes().to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Base64 => write!(f, "base64"),
+            Encoding::Hex => write!(f, "hex"),
+            Encoding::String => write!(f, "string"),
+        }
+    }
+}
+
+impl Argument {
+    /// The Lua value passed to the statement. Names of `'<data>'` and
+    /// `'<key>'` parameters are looked up in `ACK`, then `IN`, and in
+    /// `KEYS`, which fails if there is no such value. Looking up `ACK`
+    /// first gives access to the item of a `Foreach` block
+    pub fn to_lua<'lua>(&self, ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        let lookup = |globals: &[&str], name: &str| -> rlua::Result<Value<'lua>> {
+            for global in globals {
+                let table: Table = ctx.globals().get(*global)?;
+                match table.get(name)? {
+                    Value::Nil => continue,
+                    value => return Ok(value),
+                }
+            }
+            Err(rlua::Error::RuntimeError(format!(
+                "'{}' is not in {}",
+                name,
+                globals.join(" or ")
+            )))
+        };
+        match self {
+            Argument::String(text) => Ok(Value::String(ctx.create_string(text)?)),
+            Argument::Integer(integer) => Ok(Value::Integer(*integer)),
+            Argument::Number(number) => Ok(Value::Number(*number)),
+            Argument::Octet(octet) => Ok(Value::UserData(ctx.create_userdata(octet.clone())?)),
+            Argument::Data(name) => lookup(&["ACK", "IN"], name),
+            Argument::Key(name) => lookup(&["KEYS"], name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(Param::from_placeholder(""), Ok(Param::String));
+        assert_eq!(Param::from_placeholder("<number>"), Ok(Param::Number));
+        assert_eq!(
+            Param::from_placeholder("<octet>"),
+            Ok(Param::Octet(Encoding::Base64))
+        );
+        assert_eq!(
+            Param::from_placeholder("<octet:hex>"),
+            Ok(Param::Octet(Encoding::Hex))
+        );
+        assert_eq!(Param::from_placeholder("<key>"), Ok(Param::Key));
+        assert!(Param::from_placeholder("<octet:base32>").is_err());
+        assert!(Param::from_placeholder("<numbr>").is_err());
+    }
+
+    #[test]
+    fn parse_arguments() {
+        match Param::Number.parse("42") {
+            Ok(Argument::Integer(42)) => {}
+            arg => panic!("expected integer, got {:?}", arg),
+        }
+        match Param::Number.parse("1.5") {
+            Ok(Argument::Number(number)) => assert!((number - 1.5).abs() < std::f64::EPSILON),
+            arg => panic!("expected number, got {:?}", arg),
+        }
+        assert!(Param::Number.parse("one").is_err());
+        match Param::Octet(Encoding::Hex).parse("00fF") {
+            Ok(Argument::Octet(octet)) => assert_eq!(&octet[..], &[0, 255]),
+            arg => panic!("expected octet, got {:?}", arg),
+        }
+        assert!(Param::Octet(Encoding::Hex).parse("0").is_err());
+        assert!(Param::Octet(Encoding::Base64).parse("a+b").is_err());
+        assert!(Param::Data.parse("").is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use super::statement::{normalize, Phase};
+use crate::ZencodeError;
+
+/// What a line of a Zencode script declares
+#[derive(Debug, PartialEq)]
+pub(crate) enum Kind<'a> {
+    /// `Scenario 'name'`, optionally followed by `version '1.2'` and
+    /// `sha256 '<hex>'` to pin the scenario
+    Scenario {
+        name: String,
+        version: Option<String>,
+        sha256: Option<String>,
+    },
+    /// A statement of a phase, without the leading keyword
+    Statement(Phase, &'a str),
+    /// `If <statement>`, a statement of the current phase used as condition
+    If(Phase, &'a str),
+    /// `EndIf`
+    EndIf,
+    /// `Foreach 'item' in 'list'`
+    Foreach { item: String, list: String },
+    /// `EndForeach`
+    EndForeach,
+}
+
+/// A non empty line of a Zencode script
+#[derive(Debug, PartialEq)]
+pub(crate) struct Line<'a> {
+    /// The 1-based line number
+    pub number: usize,
+    /// The trimmed line as written in the script
+    pub source: &'a str,
+    pub kind: Kind<'a>,
+}
+
+impl<'a> Line<'a> {
+    /// Create a [`ZencodeError`] located at the line
+    pub fn error<S: Into<String>>(&self, message: S) -> ZencodeError {
+        ZencodeError {
+            line: Some(self.number),
+            statement: Some(self.source.to_string()),
+            phase: match self.kind {
+                Kind::Statement(phase, _) | Kind::If(phase, _) => Some(phase),
+                _ => None,
+            },
+            ..ZencodeError::new(message)
+        }
+    }
+}
+
+/// Split a Zencode script into lines, resolving `And` and `If` to the phase
+/// of the previous statement. Phases may be skipped but not go backwards.
+/// Empty lines and lines starting with `#` are ignored. Whether blocks are
+/// closed is not checked here
+pub(crate) fn lines(script: &str) -> Result<Vec<Line>, ZencodeError> {
+    let mut lines = Vec::new();
+    let mut phase: Option<Phase> = None;
+    for (index, source) in script.lines().enumerate() {
+        let source = source.trim();
+        if source.is_empty() || source.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = match source.find(char::is_whitespace) {
+            Some(pos) => (&source[..pos], source[pos..].trim_start()),
+            None => (source, ""),
+        };
+        let current = phase;
+        let error = |message: String| ZencodeError {
+            line: Some(index + 1),
+            statement: Some(source.to_string()),
+            phase: current,
+            ..ZencodeError::new(message)
+        };
+        let kind = if keyword.eq_ignore_ascii_case("scenario") {
+            scenario(rest).map_err(error)?
+        } else if keyword.eq_ignore_ascii_case("and") || keyword.eq_ignore_ascii_case("if") {
+            match current {
+                Some(current) if keyword.eq_ignore_ascii_case("if") => Kind::If(current, rest),
+                Some(current) => Kind::Statement(current, rest),
+                None => {
+                    return Err(error(format!(
+                        "'{}' has to follow a Given, When or Then statement",
+                        keyword
+                    )))
+                }
+            }
+        } else if keyword.eq_ignore_ascii_case("endif") && rest.is_empty() {
+            Kind::EndIf
+        } else if keyword.eq_ignore_ascii_case("endforeach") && rest.is_empty() {
+            Kind::EndForeach
+        } else if keyword.eq_ignore_ascii_case("foreach") {
+            let mut args = arguments(rest).into_iter();
+            match (args.next(), args.next(), normalize(rest) == "'' in ''") {
+                (Some(item), Some(list), true) => Kind::Foreach { item, list },
+                _ => return Err(error("expecting Foreach 'item' in 'list'".to_string())),
+            }
+        } else {
+            match Phase::from_keyword(keyword) {
+                Some(next) => {
+                    if let Some(current) = current {
+                        if next < current {
+                            return Err(error(format!(
+                                "'{}' can not follow a '{}' statement",
+                                next, current
+                            )));
+                        }
+                    }
+                    phase = Some(next);
+                    Kind::Statement(next, rest)
+                }
+                None => {
+                    return Err(error(format!(
+                        "unknown keyword '{}', expecting Scenario, Given, When, Then, And, \
+                         If, EndIf, Foreach or EndForeach",
+                        keyword
+                    )))
+                }
+            }
+        };
+        lines.push(Line {
+            number: index + 1,
+            source,
+            kind,
+        });
+    }
+    Ok(lines)
+}
+
+/// Parse `'name' [version '1.2'] [sha256 '<hex>']`
+fn scenario(rest: &str) -> Result<Kind, String> {
+    // quoted text is at odd indices, the keywords between at even ones
+    let parts: Vec<&str> = rest.split('\'').collect();
+    if parts.len() < 3 || parts.len() % 2 == 0 || !parts[0].trim().is_empty() {
+        return Err("expecting a quoted scenario name".to_string());
+    }
+    let (mut version, mut sha256) = (None, None);
+    for pair in parts[2..parts.len() - 1].chunks(2) {
+        let (keyword, value) = (pair[0].trim(), pair[1].to_string());
+        let pin = if keyword.eq_ignore_ascii_case("version") {
+            &mut version
+        } else if keyword.eq_ignore_ascii_case("sha256") {
+            &mut sha256
+        } else {
+            return Err(format!(
+                "unknown scenario option '{}', expecting version or sha256",
+                keyword
+            ));
+        };
+        if pin.is_some() {
+            return Err(format!("scenario option '{}' is given twice", keyword));
+        }
+        *pin = Some(value);
+    }
+    if !parts[parts.len() - 1].trim().is_empty() {
+        return Err("expecting a quoted value after a scenario option".to_string());
+    }
+    Ok(Kind::Scenario {
+        name: parts[1].to_string(),
+        version,
+        sha256,
+    })
+}
+
+/// The quoted parameters of a statement
+pub(crate) fn arguments(statement: &str) -> Vec<String> {
+    statement
+        .split('\'')
+        .skip(1)
+        .step_by(2)
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lines() {
+        let script = r#"
+Scenario 'hello'
+# a comment
+Given that my name is 'Julian'
+Then say hello
+and print all data
+"#;
+        let lines = lines(script).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0].kind,
+            Kind::Scenario {
+                name: "hello".to_string(),
+                version: None,
+                sha256: None
+            }
+        );
+        assert_eq!(
+            lines[1].kind,
+            Kind::Statement(Phase::Given, "that my name is 'Julian'")
+        );
+        assert_eq!(lines[3].number, 6);
+        assert_eq!(lines[3].source, "and print all data");
+        assert_eq!(
+            lines[3].kind,
+            Kind::Statement(Phase::Then, "print all data")
+        );
+    }
+
+    #[test]
+    fn phase_order() {
+        let err = lines("Then say hello\nGiven nothing").unwrap_err();
+        assert_eq!(err.line, Some(2));
+        assert_eq!(err.phase, Some(Phase::Then));
+        assert!(lines("Given nothing\nThen say hello").is_ok());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(lines("And say hello").unwrap_err().line, Some(1));
+        assert_eq!(lines("\nSay hello").unwrap_err().line, Some(2));
+        assert_eq!(lines("Scenario hello").unwrap_err().line, Some(1));
+    }
+
+    #[test]
+    fn pinned_scenario() {
+        let lines = lines("Scenario 'hello' version '1.2' SHA256 'abc'").unwrap();
+        assert_eq!(
+            lines[0].kind,
+            Kind::Scenario {
+                name: "hello".to_string(),
+                version: Some("1.2".to_string()),
+                sha256: Some("abc".to_string())
+            }
+        );
+        for script in &[
+            "Scenario 'hello' version",
+            "Scenario 'hello' version '1' version '2'",
+            "Scenario 'hello' release '1'",
+            "Scenario 'hello' '1'",
+        ] {
+            assert_eq!(lines(script).unwrap_err().line, Some(1));
+        }
+    }
+
+    #[test]
+    fn blocks() {
+        let script = r#"
+Given nothing
+If I have 'a'
+Foreach 'x' in 'list'
+and print 'x'
+EndForeach
+endif
+"#;
+        let lines = lines(script).unwrap();
+        assert_eq!(lines[1].kind, Kind::If(Phase::Given, "I have 'a'"));
+        assert_eq!(
+            lines[2].kind,
+            Kind::Foreach {
+                item: "x".to_string(),
+                list: "list".to_string()
+            }
+        );
+        assert_eq!(lines[3].kind, Kind::Statement(Phase::Given, "print 'x'"));
+        assert_eq!(lines[4].kind, Kind::EndForeach);
+        assert_eq!(lines[5].kind, Kind::EndIf);
+        assert_eq!(lines("If I have 'a'").unwrap_err().line, Some(1));
+        assert_eq!(
+            lines("Given nothing\nForeach 'x' of 'list'")
+                .unwrap_err()
+                .line,
+            Some(2)
+        );
+        assert_eq!(lines("Given nothing\nEndIf now").unwrap_err().line, Some(2));
+    }
+
+    #[test]
+    fn quoted_arguments() {
+        assert_eq!(
+            arguments("I want to add 'a' with 'b'"),
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert_eq!(arguments("say ''"), vec!["".to_string()]);
+        assert!(arguments("say hello").is_empty());
+    }
+}
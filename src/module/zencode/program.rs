@@ -0,0 +1,270 @@
+use super::param::Argument;
+use super::parser::{Kind, Line};
+use super::statement::{Handler, Statement};
+use crate::{Error, Result, ZencodeError};
+use rlua::{Context, Function, MultiValue, Table, Value};
+
+/// Global holding the traceback of the statements run by `ZEN:run`
+pub(crate) const TRACEBACK: &str = "ZEN_traceback";
+
+/// A parsed statement of a Zencode script
+#[derive(Clone)]
+pub(crate) struct Step {
+    pub line: usize,
+    pub source: String,
+    pub statement: Statement,
+    pub args: Vec<Argument>,
+}
+
+impl Step {
+    /// Run the handler of the statement, returning its result
+    fn call<'lua>(&self, ctx: Context<'lua>) -> Result<Value<'lua>> {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_lua(ctx))
+            .collect::<rlua::Result<_>>()?;
+        let args = MultiValue::from_vec(args);
+        match self.statement.handler {
+            Handler::Lua(ref key) => Ok(ctx.registry_value::<Function>(key)?.call(args)?),
+            Handler::Rust(ref handler) => handler(ctx, args),
+        }
+    }
+
+    /// Attach the location of the step to an error raised by its handler.
+    /// Exceeded limits are passed on unchanged
+    fn error(&self, err: Error) -> Error {
+        match err {
+            Error::Limit(_) => err,
+            err => Error::ZencodeRun(ZencodeError {
+                line: Some(self.line),
+                statement: Some(self.source.clone()),
+                phase: Some(self.statement.phase),
+                scenario: self.statement.scenario.clone(),
+                ..ZencodeError::new(err.to_string())
+            }),
+        }
+    }
+}
+
+/// A parsed Zencode script is a list of nodes, which are statements or
+/// blocks of nested nodes
+#[derive(Clone)]
+pub(crate) enum Node {
+    Step(Step),
+    /// `If <condition>` ... `EndIf`, running the body if the condition
+    /// returned a value other than `nil` or `false`
+    If {
+        condition: Step,
+        body: Vec<Node>,
+    },
+    /// `Foreach 'item' in 'list'` ... `EndForeach`, running the body with
+    /// `ACK[item]` set to each value of the array `list` in `ACK` or `IN`
+    Foreach {
+        line: usize,
+        source: String,
+        scenario: Option<String>,
+        item: String,
+        list: String,
+        body: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn body_mut(&mut self) -> Option<&mut Vec<Node>> {
+        match self {
+            Node::Step(_) => None,
+            Node::If { body, .. } | Node::Foreach { body, .. } => Some(body),
+        }
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            Node::Step(_) => "",
+            Node::If { .. } => "If",
+            Node::Foreach { .. } => "Foreach",
+        }
+    }
+
+    fn location(&self) -> ZencodeError {
+        match self {
+            Node::Step(step)
+            | Node::If {
+                condition: step, ..
+            } => ZencodeError {
+                line: Some(step.line),
+                statement: Some(step.source.clone()),
+                phase: Some(step.statement.phase),
+                ..ZencodeError::default()
+            },
+            Node::Foreach {
+                line,
+                source,
+                scenario,
+                ..
+            } => ZencodeError {
+                line: Some(*line),
+                statement: Some(source.clone()),
+                scenario: scenario.clone(),
+                ..ZencodeError::default()
+            },
+        }
+    }
+}
+
+/// Nests the nodes of a script into their blocks while it is parsed
+#[derive(Default)]
+pub(crate) struct Builder {
+    nodes: Vec<Node>,
+    open: Vec<Node>,
+}
+
+impl Builder {
+    /// Add a statement or open a block
+    pub fn push(&mut self, node: Node) {
+        match node {
+            Node::Step(_) => self.body().push(node),
+            block => self.open.push(block),
+        }
+    }
+
+    /// Close the innermost block with `EndIf` or `EndForeach`
+    pub fn close(&mut self, line: &Line) -> std::result::Result<(), ZencodeError> {
+        let (keyword, end) = match line.kind {
+            Kind::EndForeach => ("Foreach", "EndForeach"),
+            _ => ("If", "EndIf"),
+        };
+        match self.open.pop() {
+            Some(ref block) if block.keyword() != keyword => Err(line.error(format!(
+                "'{}' can not close the '{}' block of line {}",
+                end,
+                block.keyword(),
+                block.location().line.unwrap_or_default()
+            ))),
+            Some(block) => {
+                self.body().push(block);
+                Ok(())
+            }
+            None => Err(line.error(format!("'{}' without '{}'", end, keyword))),
+        }
+    }
+
+    /// The parsed nodes, failing if a block is not closed
+    pub fn finish(self) -> std::result::Result<Vec<Node>, ZencodeError> {
+        match self.open.last() {
+            Some(block) => {
+                let end = match block {
+                    Node::Foreach { .. } => "EndForeach",
+                    _ => "EndIf",
+                };
+                Err(ZencodeError {
+                    message: format!("'{}' block is not closed with '{}'", block.keyword(), end),
+                    ..block.location()
+                })
+            }
+            None => Ok(self.nodes),
+        }
+    }
+
+    fn body(&mut self) -> &mut Vec<Node> {
+        match self.open.last_mut() {
+            Some(block) => block.body_mut().unwrap(),
+            None => &mut self.nodes,
+        }
+    }
+}
+
+/// Runs the nodes of a parsed script, appending each statement which is run
+/// to the traceback
+pub(crate) struct Runner<'lua> {
+    ctx: Context<'lua>,
+    verbosity: u8,
+    traceback: String,
+}
+
+impl<'lua> Runner<'lua> {
+    pub fn new(ctx: Context<'lua>, verbosity: u8) -> Result<Self> {
+        ctx.globals().set(TRACEBACK, "")?;
+        Ok(Runner {
+            ctx,
+            verbosity,
+            traceback: String::new(),
+        })
+    }
+
+    pub fn run(&mut self, nodes: &[Node]) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Step(step) => {
+                    self.trace(&step.source)?;
+                    step.call(self.ctx).map_err(|err| step.error(err))?;
+                }
+                Node::If { condition, body } => {
+                    self.trace(&condition.source)?;
+                    match condition
+                        .call(self.ctx)
+                        .map_err(|err| condition.error(err))?
+                    {
+                        Value::Nil | Value::Boolean(false) => {}
+                        _ => self.run(body)?,
+                    }
+                }
+                Node::Foreach {
+                    source,
+                    item,
+                    list,
+                    body,
+                    ..
+                } => {
+                    self.trace(source)?;
+                    let values = self.list(list).map_err(|message| {
+                        Error::ZencodeRun(ZencodeError {
+                            message,
+                            ..node.location()
+                        })
+                    })?;
+                    let ack: Table = self.ctx.globals().get("ACK")?;
+                    let previous: Value = ack.get(item.as_str())?;
+                    for value in values {
+                        let ack: Table = self.ctx.globals().get("ACK")?;
+                        ack.set(item.as_str(), value)?;
+                        self.run(body)?;
+                    }
+                    let ack: Table = self.ctx.globals().get("ACK")?;
+                    ack.set(item.as_str(), previous)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The values of the array `name` in `ACK` or `IN`
+    fn list(&self, name: &str) -> std::result::Result<Vec<Value<'lua>>, String> {
+        let lookup = |global: &str| -> rlua::Result<Value<'lua>> {
+            let table: Table = self.ctx.globals().get(global)?;
+            table.get(name)
+        };
+        let value = match lookup("ACK").map_err(|err| err.to_string())? {
+            Value::Nil => lookup("IN").map_err(|err| err.to_string())?,
+            value => value,
+        };
+        match value {
+            Value::Table(table) => table
+                .sequence_values()
+                .collect::<rlua::Result<_>>()
+                .map_err(|err| err.to_string()),
+            Value::Nil => Err(format!("'{}' is not in ACK or IN", name)),
+            _ => Err(format!("'{}' is not an array", name)),
+        }
+    }
+
+    fn trace(&mut self, source: &str) -> Result<()> {
+        if self.verbosity > 0 {
+            self.traceback.push_str("    -> ");
+            self.traceback.push_str(source);
+            self.traceback.push('\n');
+            self.ctx.globals().set(TRACEBACK, self.traceback.as_str())?;
+        }
+        Ok(())
+    }
+}
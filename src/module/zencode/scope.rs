@@ -0,0 +1,48 @@
+use crate::Result;
+use rlua::{Context, Table, Value};
+
+/// The Zencode state a statement implemented in Rust runs in.
+///
+/// `ACK`, `IN` and `KEYS` are Lua tables, so values set on them are visible
+/// to all following statements. Tables or values assigned to the fields are
+/// written back to the globals after the statement returned
+pub struct Scope<'lua> {
+    ctx: Context<'lua>,
+    /// `ACK`, the values acknowledged by previous statements
+    pub ack: Table<'lua>,
+    /// `IN`, the data passed to `ZEN:run`
+    pub input: Table<'lua>,
+    /// `KEYS`, the keys passed to `ZEN:run`
+    pub keys: Table<'lua>,
+    /// `OUT`, the value returned by `ZEN:run`
+    pub out: Value<'lua>,
+}
+
+impl<'lua> Scope<'lua> {
+    /// Read the scope from the globals of the Lua VM
+    pub(crate) fn load(ctx: Context<'lua>) -> Result<Self> {
+        let globals = ctx.globals();
+        Ok(Scope {
+            ctx,
+            ack: globals.get("ACK")?,
+            input: globals.get("IN")?,
+            keys: globals.get("KEYS")?,
+            out: globals.get("OUT")?,
+        })
+    }
+
+    /// Write the scope back to the globals of the Lua VM
+    pub(crate) fn store(self) -> Result<()> {
+        let globals = self.ctx.globals();
+        globals.set("ACK", self.ack)?;
+        globals.set("IN", self.input)?;
+        globals.set("KEYS", self.keys)?;
+        globals.set("OUT", self.out)?;
+        Ok(())
+    }
+
+    /// The Lua context, to create new Lua values
+    pub fn context(&self) -> Context<'lua> {
+        self.ctx
+    }
+}
@@ -0,0 +1,264 @@
+use super::param::Param;
+use super::parser::arguments;
+use crate::{Result, ZencodeError};
+use rlua::{Context, MultiValue, RegistryKey, Value};
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The phase of a Zencode statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    /// Statements starting with `Given`, which read the input
+    Given,
+    /// Statements starting with `When`, which process the input
+    When,
+    /// Statements starting with `Then`, which write the output
+    Then,
+}
+
+impl Phase {
+    pub(crate) const ALL: [Phase; 3] = [Phase::Given, Phase::When, Phase::Then];
+
+    /// The keyword starting a statement, which is also the global function
+    /// registering statements of the phase
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Phase::Given => "Given",
+            Phase::When => "When",
+            Phase::Then => "Then",
+        }
+    }
+
+    /// Parse a statement keyword, ignoring case
+    pub fn from_keyword(keyword: &str) -> Option<Phase> {
+        Phase::ALL
+            .iter()
+            .cloned()
+            .find(|phase| phase.keyword().eq_ignore_ascii_case(keyword))
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.keyword())
+    }
+}
+
+/// A statement implemented in Rust, called with the converted parameters.
+/// The returned value is the result of the condition of an `If` block
+pub(crate) type RustHandler =
+    Arc<dyn for<'lua> Fn(Context<'lua>, MultiValue<'lua>) -> Result<Value<'lua>> + Send + Sync>;
+
+pub(crate) fn rust_handler<F>(handler: F) -> RustHandler
+where
+    F: 'static + Send + Sync + for<'lua> Fn(Context<'lua>, MultiValue<'lua>) -> Result<Value<'lua>>,
+{
+    Arc::new(handler)
+}
+
+/// The function run for a statement
+#[derive(Clone)]
+pub(crate) enum Handler {
+    /// A Lua function stored in the registry of the Lua VM
+    Lua(Arc<RegistryKey>),
+    /// A Rust closure
+    Rust(RustHandler),
+}
+
+/// A statement registered with `Given`, `When` or `Then`
+#[derive(Clone)]
+pub(crate) struct Statement {
+    pub phase: Phase,
+    pub pattern: String,
+    /// The types of the quoted parameters of the pattern
+    pub params: Vec<Param>,
+    pub scenario: Option<String>,
+    pub handler: Handler,
+}
+
+impl Statement {
+    /// Create a statement, parsing the placeholders of the pattern
+    pub fn new(
+        phase: Phase,
+        pattern: String,
+        scenario: Option<String>,
+        handler: Handler,
+    ) -> std::result::Result<Statement, ZencodeError> {
+        let params = arguments(&pattern)
+            .iter()
+            .map(|placeholder| Param::from_placeholder(placeholder))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|message| ZencodeError {
+                statement: Some(pattern.clone()),
+                phase: Some(phase),
+                scenario: scenario.clone(),
+                ..ZencodeError::new(message)
+            })?;
+        Ok(Statement {
+            phase,
+            pattern,
+            params,
+            scenario,
+            handler,
+        })
+    }
+}
+
+/// The statements registered in a Lua VM, by phase and normalized pattern
+#[derive(Default)]
+pub(crate) struct Statements(HashMap<(Phase, String), Statement>);
+
+impl Statements {
+    /// Register a statement, replacing a statement with the same pattern
+    pub fn register(&mut self, statement: Statement) {
+        let key = (statement.phase, normalize(&statement.pattern));
+        self.0.insert(key, statement);
+    }
+
+    /// Find the statement matching a normalized statement
+    pub fn find(&self, phase: Phase, statement: &str) -> Option<&Statement> {
+        self.0.get(&(phase, statement.to_string()))
+    }
+
+    /// The pattern of the registered statement closest to a normalized
+    /// statement, if it is similar enough. Of equally close patterns the
+    /// first in alphabetical order is returned
+    pub fn suggest(&self, phase: Phase, statement: &str) -> Option<String> {
+        self.0
+            .iter()
+            .filter(|((registered, _), _)| *registered == phase)
+            .map(|((_, pattern), registered)| {
+                let max = cmp::max(pattern.len(), statement.len());
+                (levenshtein(statement, pattern), max, registered)
+            })
+            .filter(|(distance, max, _)| *distance <= max / 2)
+            .min_by_key(|&(distance, _, registered)| (distance, registered.pattern.as_str()))
+            .map(|(_, _, registered)| registered.pattern.clone())
+    }
+}
+
+/// Replace quoted parameters with `''`, lowercase and collapse whitespace,
+/// so that a statement can be compared with a registered pattern
+pub(crate) fn normalize(statement: &str) -> String {
+    let mut normalized = String::with_capacity(statement.len());
+    let mut quoted = false;
+    for c in statement.chars() {
+        if c == '\'' {
+            if !quoted {
+                normalized.push_str("''");
+            }
+            quoted = !quoted;
+        } else if !quoted {
+            normalized.extend(c.to_lowercase());
+        }
+    }
+    normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The edit distance between two strings
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + cmp::min(diagonal, cmp::min(row[j], above))
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlua::Lua;
+
+    #[test]
+    fn normalize_parameters() {
+        assert_eq!(
+            normalize("That my  name is 'Julian Popescu'"),
+            "that my name is ''"
+        );
+        assert_eq!(normalize("that my name is ''"), "that my name is ''");
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("say hello", "say hello"), 0);
+        assert_eq!(levenshtein("say helo", "say hello"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn register_and_suggest() {
+        let lua = Lua::new();
+        let handler = lua.context(|ctx| ctx.create_registry_value(true)).unwrap();
+        let mut statements = Statements::default();
+        statements.register(
+            Statement::new(
+                Phase::Then,
+                "say hello to ''".to_string(),
+                None,
+                Handler::Lua(Arc::new(handler)),
+            )
+            .unwrap(),
+        );
+        assert!(statements
+            .find(Phase::Then, &normalize("say Hello to 'Julian'"))
+            .is_some());
+        assert!(statements.find(Phase::Given, "say hello to ''").is_none());
+        assert_eq!(
+            statements.suggest(Phase::Then, &normalize("say helo to 'Julian'")),
+            Some("say hello to ''".to_string())
+        );
+        assert_eq!(statements.suggest(Phase::Then, "do something else"), None);
+    }
+
+    #[test]
+    fn suggest_ties() {
+        let lua = Lua::new();
+        let handler = lua.context(|ctx| ctx.create_registry_value(true)).unwrap();
+        let handler = Handler::Lua(Arc::new(handler));
+        let mut statements = Statements::default();
+        for pattern in &["say hellp", "say hello", "say hellq"] {
+            statements.register(
+                Statement::new(Phase::Then, pattern.to_string(), None, handler.clone()).unwrap(),
+            );
+        }
+        assert_eq!(
+            statements.suggest(Phase::Then, "say hellx"),
+            Some("say hello".to_string())
+        );
+    }
+
+    #[test]
+    fn typed_pattern() {
+        let lua = Lua::new();
+        let handler = lua.context(|ctx| ctx.create_registry_value(true)).unwrap();
+        let handler = Handler::Lua(Arc::new(handler));
+        let statement = Statement::new(
+            Phase::When,
+            "I add '<number>' to '<data>'".to_string(),
+            None,
+            handler.clone(),
+        )
+        .unwrap();
+        assert_eq!(statement.params, vec![Param::Number, Param::Data]);
+        assert_eq!(normalize(&statement.pattern), "i add '' to ''");
+        let err = Statement::new(Phase::When, "I add '<int>'".to_string(), None, handler)
+            .err()
+            .unwrap();
+        assert_eq!(err.phase, Some(Phase::When));
+        assert_eq!(err.statement, Some("I add '<int>'".to_string()));
+    }
+}
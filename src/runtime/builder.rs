@@ -0,0 +1,363 @@
+use super::{console::Console, sandbox, DefaultRuntime, Limits, ZencodeRuntime};
+use crate::module::set_random_source;
+use crate::{
+    prelude::*, Error, Importer, RandomSource, Result, ScenarioLinker, ScenarioLoader,
+    SystemRandomSource, Zencode,
+};
+use rlua::{Context, Lua, StdLib};
+use std::sync::Arc;
+
+type Linker = Box<dyn ScenarioLinker + Send + Sync>;
+
+/// A builder to configure and create a [`DefaultRuntime`] or a
+/// [`ZencodeRuntime`].
+///
+/// By default the builder creates a sandboxed Lua VM with the [`Importer`]
+/// available as `import` for all builtin modules, no other preloaded
+/// modules and no execution limits.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use zenroom_minimal::{prelude::*, Json, Limits, Result, RuntimeBuilder};
+/// # fn main() -> Result<()> {
+/// let res = RuntimeBuilder::new()
+///     .preload::<Json>()
+///     .importable(&["octet"])
+///     .limits(Limits::new().timeout(Duration::from_secs(1)))
+///     .build_default()?
+///     .load("return JSON.encode({a = 1})")?
+///     .eval()?;
+/// # assert_eq!(Some("{\"a\":1}".to_string()), res);
+/// # Ok(())
+/// # }
+/// ```
+pub struct RuntimeBuilder {
+    libs: StdLib,
+    importer: Option<Importer>,
+    preload: Vec<fn(Context) -> LuaResult<()>>,
+    loader: Option<ScenarioLoader<Linker>>,
+    zencode: Zencode,
+    verbosity: u8,
+    forward_to_log: bool,
+    limits: Limits,
+    random: Arc<dyn RandomSource>,
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        RuntimeBuilder {
+            libs: sandbox::libs(),
+            importer: Some(Importer::default()),
+            preload: Vec::new(),
+            loader: None,
+            zencode: Zencode::default(),
+            verbosity: 1,
+            forward_to_log: false,
+            limits: Limits::default(),
+            random: Arc::new(SystemRandomSource::default()),
+        }
+    }
+}
+
+impl RuntimeBuilder {
+    /// Create a new [`RuntimeBuilder`] with the default configuration
+    pub fn new() -> Self {
+        RuntimeBuilder::default()
+    }
+
+    /// Select the Lua standard libraries to load. Defaults to the sandboxed
+    /// set of `base`, `coroutine`, `table`, `string`, `utf8` and `math`.
+    ///
+    /// Building fails with [`Error::Config`] if `libs` contains
+    /// [`StdLib::DEBUG`], which can not be sandboxed
+    pub fn libs(mut self, libs: StdLib) -> Self {
+        self.libs = libs;
+        self
+    }
+
+    /// Set the [`Importer`] exposed as `import`, to register custom
+    /// modules or remove builtin ones
+    pub fn importer(mut self, importer: Importer) -> Self {
+        self.importer = Some(importer);
+        self
+    }
+
+    /// Only allow the modules with the given identifiers to be imported
+    /// with `import`. Does not expose `import` again after
+    /// [`without_importer`](RuntimeBuilder::without_importer)
+    pub fn importable(mut self, identifiers: &[&str]) -> Self {
+        self.importer = self.importer.map(|importer| importer.retain(identifiers));
+        self
+    }
+
+    /// Do not expose `import` at all
+    pub fn without_importer(mut self) -> Self {
+        self.importer = None;
+        self
+    }
+
+    /// Preload the module as a global under its [`DefaultModule::GLOBAL_VAR`]
+    pub fn preload<M: DefaultModule>(mut self) -> Self {
+        self.preload.push(M::import_module);
+        self
+    }
+
+    /// Set the [`ScenarioLoader`] used by the [`ZencodeRuntime`] to load
+    /// scenarios. Defaults to [`ScenarioLoader::default`]
+    pub fn scenario_loader<L>(mut self, loader: ScenarioLoader<L>) -> Self
+    where
+        L: 'static + ScenarioLinker + Sync + Send,
+    {
+        self.loader = Some(loader.boxed());
+        self
+    }
+
+    /// Set the [`Zencode`] module of the [`ZencodeRuntime`], to provide
+    /// statements implemented in Rust
+    pub fn zencode(mut self, zencode: Zencode) -> Self {
+        self.zencode = zencode;
+        self
+    }
+
+    /// Set the [`ScenarioLinker`] used by the [`ZencodeRuntime`] to load
+    /// scenarios
+    pub fn scenario_linker<L>(self, linker: L) -> Self
+    where
+        L: 'static + ScenarioLinker + Sync + Send,
+    {
+        self.scenario_loader(ScenarioLoader::new(linker))
+    }
+
+    /// Set the verbosity passed to `ZEN:begin`. Defaults to `1`
+    pub fn verbosity(mut self, verbosity: u8) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Forward the output of `print`, `warn` and `trace` to the `log`
+    /// facade under the `zenroom_minimal::lua` target, in addition to
+    /// capturing it in the [`ExecutionResult`](crate::ExecutionResult).
+    /// Defaults to `false`
+    pub fn forward_to_log(mut self, forward: bool) -> Self {
+        self.forward_to_log = forward;
+        self
+    }
+
+    /// Set the execution [`Limits`] of the runtime
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the [`RandomSource`] used by modules in the runtime, like
+    /// `OCTET.random`. Key generation and signing of the
+    /// [`Keyring`](crate::Keyring) always use the operating system's secure
+    /// random number generator, as ring does not accept other sources
+    pub fn random_source<R: 'static + RandomSource>(mut self, random: R) -> Self {
+        self.random = Arc::new(random);
+        self
+    }
+
+    fn build_lua(&self) -> Result<(Lua, Console)> {
+        if self.libs.contains(StdLib::DEBUG) {
+            return Err(Error::Config(
+                "the debug library can not be loaded".to_string(),
+            ));
+        }
+        let lua = Lua::new_with(self.libs);
+        let console = Console::new(self.forward_to_log);
+        lua.context(|ctx| -> Result<()> {
+            Limits::protect_calls(ctx)?;
+            console.install(ctx)?;
+            if let Some(ref importer) = self.importer {
+                ctx.globals()
+                    .set(Importer::GLOBAL_VAR, importer.clone().build_module(ctx)?)?;
+            }
+            for preload in &self.preload {
+                preload(ctx)?;
+            }
+            set_random_source(ctx, self.random.clone())?;
+            Limits::track_memory(ctx)?;
+            Ok(())
+        })?;
+        Ok((lua, console))
+    }
+
+    /// Build a [`DefaultRuntime`]
+    pub fn build_default(self) -> Result<DefaultRuntime> {
+        let (lua, console) = self.build_lua()?;
+        let mut runtime = DefaultRuntime::from_lua(lua, console);
+        runtime.set_limits(self.limits);
+        Ok(runtime)
+    }
+
+    /// Build a [`ZencodeRuntime`]. Functions which load Lua code are
+    /// removed from the VM after the setup
+    pub fn build_zencode(mut self) -> Result<ZencodeRuntime> {
+        let (lua, console) = self.build_lua()?;
+        let loader = self
+            .loader
+            .take()
+            .unwrap_or_else(|| ScenarioLoader::default().boxed());
+        let zencode = self.zencode;
+        lua.context(|ctx| -> Result<()> {
+            ctx.globals()
+                .set(ScenarioLoader::GLOBAL_VAR, loader.build_module(ctx)?)?;
+            ctx.globals()
+                .set(Zencode::GLOBAL_VAR, zencode.build_module(ctx)?)?;
+            sandbox::strip_globals(ctx)?;
+            Ok(())
+        })?;
+        let mut runtime = ZencodeRuntime::from_lua(lua, console);
+        runtime.set_verbosity(self.verbosity)?;
+        runtime.set_limits(self.limits);
+        Ok(runtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Json, OctetClass};
+    use std::sync::Mutex;
+
+    struct ZeroRandomSource;
+
+    impl RandomSource for ZeroRandomSource {
+        fn fill(&self, dest: &mut [u8]) -> Result<()> {
+            for byte in dest.iter_mut() {
+                *byte = 0;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn importable() {
+        let mut runtime = RuntimeBuilder::new()
+            .importable(&[OctetClass::IDENTIFIER])
+            .build_default()
+            .unwrap();
+        runtime
+            .load("return tostring(import('octet') ~= nil)")
+            .unwrap();
+        assert_eq!(runtime.eval().unwrap(), Some("true".to_string()));
+        match runtime.load("return import('json')").unwrap().eval() {
+            Err(Error::Lua(_)) => (),
+            res => panic!("json should not be importable, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn debug_library() {
+        let res = RuntimeBuilder::new()
+            .libs(sandbox::libs() | StdLib::DEBUG)
+            .build_default();
+        match res {
+            Err(Error::Config(_)) => (),
+            Err(err) => panic!("expected config error, got {}", err),
+            Ok(_) => panic!("expected config error"),
+        }
+    }
+
+    #[test]
+    fn without_importer() {
+        let mut runtime = RuntimeBuilder::new()
+            .without_importer()
+            .preload::<Json>()
+            .build_default()
+            .unwrap();
+        let res = runtime
+            .load("return tostring(import == nil and JSON ~= nil)")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(res, Some("true".to_string()));
+    }
+
+    #[test]
+    fn random_source() {
+        let mut runtime = RuntimeBuilder::new()
+            .preload::<OctetClass>()
+            .random_source(ZeroRandomSource)
+            .build_default()
+            .unwrap();
+        let res = runtime
+            .load("return OCTET.random(4):base64()")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(res, Some("AAAAAA".to_string()));
+    }
+
+    #[test]
+    fn importable_without_importer() {
+        let mut runtime = RuntimeBuilder::new()
+            .without_importer()
+            .importable(&[OctetClass::IDENTIFIER])
+            .build_default()
+            .unwrap();
+        let res = runtime
+            .load("return tostring(import == nil)")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(res, Some("true".to_string()));
+    }
+
+    /// Collects the records logged by Lua code
+    struct TestLogger(Mutex<Vec<(log::Level, String)>>);
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "zenroom_minimal::lua"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let line = record.args().to_string();
+                self.0.lock().unwrap().push((record.level(), line));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn forward_to_log() {
+        let logger: &'static TestLogger = Box::leak(Box::new(TestLogger(Mutex::new(Vec::new()))));
+        log::set_logger(logger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut runtime = RuntimeBuilder::new()
+            .forward_to_log(true)
+            .build_default()
+            .unwrap();
+        let res = runtime
+            .load("print('out') warn('err') trace('step', 1)")
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(res.stdout, "out\n");
+        assert_eq!(res.stderr, "[W] err\n[T] step\t1\n");
+        assert_eq!(
+            *logger.0.lock().unwrap(),
+            vec![
+                (log::Level::Info, "out".to_string()),
+                (log::Level::Warn, "err".to_string()),
+                (log::Level::Trace, "step\t1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn zencode() {
+        let mut runtime = RuntimeBuilder::new()
+            .without_importer()
+            .verbosity(0)
+            .build_zencode()
+            .unwrap();
+        let res = runtime.load("").unwrap().eval().unwrap();
+        assert_eq!(Some("{}".to_string()), res);
+    }
+}
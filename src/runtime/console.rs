@@ -0,0 +1,111 @@
+use rlua::{Context, Function, Result, Value, Variadic};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// Target of the records forwarded to the `log` facade
+const LOG_TARGET: &str = "zenroom_minimal::lua";
+
+/// Captures the output written by Lua code into buffers instead of writing
+/// it to the stdout and stderr of the process. Exposes `print` writing to
+/// stdout and `warn` and `trace` writing to stderr
+#[derive(Clone, Default)]
+pub(crate) struct Console {
+    stdout: Arc<Mutex<String>>,
+    stderr: Arc<Mutex<String>>,
+    forward: bool,
+}
+
+/// The output captured by a [`Console`]
+pub(crate) struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Console {
+    /// Create a [`Console`] which also forwards each written line to the
+    /// `log` facade if `forward` is set
+    pub(crate) fn new(forward: bool) -> Self {
+        Console {
+            forward,
+            ..Console::default()
+        }
+    }
+
+    /// Replace the global `print` function and add `warn` and `trace`
+    pub(crate) fn install(&self, ctx: Context) -> Result<()> {
+        let globals = ctx.globals();
+        globals.set(
+            "print",
+            self.writer(ctx, self.stdout.clone(), "", log::Level::Info)?,
+        )?;
+        globals.set(
+            "warn",
+            self.writer(ctx, self.stderr.clone(), "[W] ", log::Level::Warn)?,
+        )?;
+        globals.set(
+            "trace",
+            self.writer(ctx, self.stderr.clone(), "[T] ", log::Level::Trace)?,
+        )
+    }
+
+    /// Create a function appending its arguments as a line to `buffer`
+    fn writer<'lua>(
+        &self,
+        ctx: Context<'lua>,
+        buffer: Arc<Mutex<String>>,
+        prefix: &'static str,
+        level: log::Level,
+    ) -> Result<Function<'lua>> {
+        let forward = self.forward;
+        ctx.create_function(move |ctx, values: Variadic<Value>| {
+            let line = Console::format(ctx, values)?;
+            if forward {
+                log::log!(target: LOG_TARGET, level, "{}", line);
+            }
+            let mut buffer = buffer.lock().unwrap();
+            buffer.push_str(prefix);
+            buffer.push_str(&line);
+            buffer.push('\n');
+            Ok(())
+        })
+    }
+
+    /// Format values like Lua's `print` does, separated by tabs
+    fn format<'lua>(ctx: Context<'lua>, values: Variadic<Value<'lua>>) -> Result<String> {
+        let tostring = ctx.globals().get::<_, Option<Function>>("tostring")?;
+        let mut parts = Vec::with_capacity(values.len());
+        for value in values.iter().cloned() {
+            let part = match tostring {
+                Some(ref tostring) => tostring.call::<_, String>(value)?,
+                None => match value {
+                    Value::Nil => "nil".to_string(),
+                    Value::Boolean(b) => b.to_string(),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Number(n) => n.to_string(),
+                    Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+                    Value::Table(_) => "table".to_string(),
+                    Value::Function(_) => "function".to_string(),
+                    Value::Thread(_) => "thread".to_string(),
+                    Value::UserData(_) | Value::LightUserData(_) => "userdata".to_string(),
+                    Value::Error(err) => err.to_string(),
+                },
+            };
+            parts.push(part);
+        }
+        Ok(parts.join("\t"))
+    }
+
+    /// Discard the captured output
+    pub(crate) fn clear(&self) {
+        self.stdout.lock().unwrap().clear();
+        self.stderr.lock().unwrap().clear();
+    }
+
+    /// Take the captured output, leaving the buffers empty
+    pub(crate) fn take(&self) -> Captured {
+        Captured {
+            stdout: mem::replace(&mut *self.stdout.lock().unwrap(), String::new()),
+            stderr: mem::replace(&mut *self.stderr.lock().unwrap(), String::new()),
+        }
+    }
+}
@@ -1,40 +1,115 @@
-use super::Runtime;
-use crate::{prelude::*, Importer};
-use rlua::{Lua, Result, StdLib};
+use super::console::Console;
+use super::{
+    ExecutionResult, Limits, MemoryUsage, ResourceUsage, Runtime, RuntimeBuilder, Timings,
+};
+use crate::{prelude::*, Error, Importer, Result};
+use rlua::{Context, Function, Lua, Value};
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::time::Instant;
 
 /// The default runtime is a basic Lua environment, sandboxed without
 /// file system or some standard OS function access. The environment
-/// is preluded with the [`Importer`] module
+/// is preluded with the [`Importer`] module, and `print`, `warn` and
+/// `trace` write to the [`ExecutionResult`] instead of stdout and stderr.
+/// Use the [`RuntimeBuilder`]
+/// for a custom setup
 pub struct DefaultRuntime {
     lua: Lua,
     source: String,
+    console: Console,
+    limits: Limits,
+    usage: Cell<ResourceUsage>,
 }
 
 impl Default for DefaultRuntime {
     fn default() -> Self {
-        // TODO make static
-        let mut libs = StdLib::empty();
-        libs.insert(StdLib::BASE);
-        libs.insert(StdLib::COROUTINE);
-        libs.insert(StdLib::TABLE);
-        libs.insert(StdLib::STRING);
-        libs.insert(StdLib::UTF8);
-        libs.insert(StdLib::MATH);
-        let lua = Lua::new_with(libs);
-        DefaultRuntime::new(lua)
+        RuntimeBuilder::new().build_default().unwrap()
     }
 }
 
 impl DefaultRuntime {
     /// Create a new [`DefaultRuntime`]
     pub fn new(lua: Lua) -> Self {
-        let runtime = DefaultRuntime {
+        let console = Console::default();
+        lua.context(Limits::protect_calls).unwrap();
+        lua.context(Importer::import_module).unwrap();
+        lua.context(Limits::track_memory).unwrap();
+        lua.context(|ctx| console.install(ctx)).unwrap();
+        DefaultRuntime::from_lua(lua, console)
+    }
+
+    /// Create a new [`DefaultRuntime`] from an already set up Lua VM
+    pub(crate) fn from_lua(lua: Lua, console: Console) -> Self {
+        DefaultRuntime {
             lua,
             source: "".to_string(),
-        };
-        runtime.lua.context(Importer::import_module).unwrap();
+            console,
+            limits: Limits::default(),
+            usage: Cell::new(ResourceUsage::default()),
+        }
+    }
+
+    /// Create a new [`DefaultRuntime`] with [`Limits`] applied to each
+    /// evaluation
+    pub fn with_limits(lua: Lua, limits: Limits) -> Self {
+        let mut runtime = DefaultRuntime::new(lua);
+        runtime.set_limits(limits);
         runtime
     }
+
+    /// Remove a module from the import cache, so that the next `import`
+    /// builds it again
+    pub fn invalidate_module(&self, identifier: &str) -> Result<()> {
+        Ok(self
+            .lua
+            .context(|ctx| Importer::invalidate(ctx, identifier))?)
+    }
+
+    /// Set the [`Limits`] applied to each evaluation
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        limits.apply(&self.lua);
+        self.limits = limits;
+        self
+    }
+
+    /// The Lua heap usage of the last evaluation
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.usage.get().memory
+    }
+
+    /// Run the loaded source within the [`Limits`] and convert the returned
+    /// value with `encode`
+    fn run<R, F>(&self, timings: &mut Timings, encode: F) -> Result<R>
+    where
+        F: for<'lua> FnOnce(Context<'lua>, Value<'lua>) -> Result<R>,
+    {
+        self.limits.guard(&self.lua, &self.usage, || {
+            self.lua.context(|ctx| {
+                let start = Instant::now();
+                let func = DefaultRuntime::compile(ctx, &self.source)?;
+                timings.parse = start.elapsed();
+
+                let start = Instant::now();
+                let value: Value = func.call(())?;
+                timings.run = start.elapsed();
+
+                let start = Instant::now();
+                let output = encode(ctx, value)?;
+                timings.encode = start.elapsed();
+                Ok(output)
+            })
+        })
+    }
+
+    /// Compile the source like [`rlua::Chunk::eval`]: as an expression if
+    /// possible, otherwise as a block of statements
+    fn compile<'lua>(ctx: Context<'lua>, source: &str) -> LuaResult<Function<'lua>> {
+        match ctx.load(&format!("return {}", source)).into_function() {
+            Ok(func) => Ok(func),
+            Err(_) => ctx.load(source).into_function(),
+        }
+    }
 }
 
 impl Runtime for DefaultRuntime {
@@ -43,15 +118,37 @@ impl Runtime for DefaultRuntime {
         Ok(self)
     }
 
-    fn eval(&self) -> Result<Option<String>> {
-        self.lua
-            .context(|lua_ctx| lua_ctx.load(&self.source).eval::<Option<String>>())
+    fn execute(&self) -> Result<ExecutionResult> {
+        let mut timings = Timings::default();
+        let output = self.run(&mut timings, |ctx, value| {
+            Ok(Option::<String>::from_lua(value, ctx)?)
+        });
+        let captured = self.console.take();
+        Ok(ExecutionResult {
+            output: output?,
+            stdout: captured.stdout,
+            stderr: captured.stderr,
+            traceback: None,
+            timings,
+            usage: self.usage.get(),
+        })
+    }
+
+    fn eval_as<T: DeserializeOwned>(&self) -> Result<T> {
+        let res = self.run(&mut Timings::default(), |_, value| {
+            rlua_serde::from_value(value).map_err(|err| Error::Encoding(err.to_string()))
+        });
+        self.console.clear();
+        res
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Limit;
+    use serde::Deserialize;
+    use std::time::Duration;
 
     #[test]
     fn empty() {
@@ -88,4 +185,189 @@ return JSON.encode({a = 1})
             .unwrap();
         assert_eq!(res, Some("{\"a\":1}".to_string()));
     }
+
+    #[test]
+    fn execute() {
+        let mut runtime = DefaultRuntime::default();
+        let res = runtime
+            .load(
+                r#"
+print("hello", 1, nil)
+print("world")
+warn("careful")
+trace("step", 2)
+return "done"
+        "#,
+            )
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(res.output, Some("done".to_string()));
+        assert_eq!(res.stdout, "hello\t1\tnil\nworld\n");
+        assert_eq!(res.stderr, "[W] careful\n[T] step\t2\n");
+        assert_eq!(res.traceback, None);
+        let res = runtime.load("return 1 + 1").unwrap().execute().unwrap();
+        assert_eq!(res.output, Some("2".to_string()));
+        assert_eq!(res.stdout, "");
+        assert_eq!(res.stderr, "");
+    }
+
+    #[test]
+    fn eval_as() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Point {
+            x: i64,
+            y: f64,
+            label: Option<String>,
+        }
+
+        let mut runtime = DefaultRuntime::default();
+        let res: Point = runtime
+            .load("return {x = 1, y = 2.5}")
+            .unwrap()
+            .eval_as()
+            .unwrap();
+        assert_eq!(
+            res,
+            Point {
+                x: 1,
+                y: 2.5,
+                label: None
+            }
+        );
+        let res: Vec<bool> = runtime
+            .load("return {true, false}")
+            .unwrap()
+            .eval_as()
+            .unwrap();
+        assert_eq!(res, vec![true, false]);
+        match runtime.load("return 'one'").unwrap().eval_as::<i64>() {
+            Err(Error::Encoding(_)) => (),
+            res => panic!("expected encoding error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn instruction_limit() {
+        let mut runtime = DefaultRuntime::default();
+        runtime.set_limits(Limits::new().instructions(10_000));
+        match runtime.load("while true do end").unwrap().eval() {
+            Err(Error::Limit(Limit::Instructions)) => (),
+            res => panic!("expected instruction limit, got {:?}", res),
+        }
+        let res = runtime.load("return 'done'").unwrap().eval().unwrap();
+        assert_eq!(res, Some("done".to_string()));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut runtime = DefaultRuntime::default();
+        runtime.set_limits(Limits::new().timeout(Duration::from_millis(50)));
+        let res = runtime
+            .load("while true do pcall(function() end) end")
+            .unwrap()
+            .eval();
+        match res {
+            Err(Error::Limit(Limit::Timeout)) => (),
+            res => panic!("expected timeout, got {:?}", res),
+        }
+        let res = runtime.load("return 'done'").unwrap().eval().unwrap();
+        assert_eq!(res, Some("done".to_string()));
+    }
+
+    #[test]
+    fn caught_limits() {
+        let mut runtime = DefaultRuntime::default();
+        let script = "while true do pcall(function() while true do end end) end";
+        runtime.set_limits(Limits::new().instructions(10_000));
+        match runtime.load(script).unwrap().eval() {
+            Err(Error::Limit(Limit::Instructions)) => (),
+            res => panic!("expected instruction limit, got {:?}", res),
+        }
+        runtime.set_limits(Limits::new().timeout(Duration::from_millis(50)));
+        let script = r#"
+while true do
+    xpcall(function()
+        coroutine.resume(coroutine.create(function() while true do end end))
+    end, function() end)
+end
+        "#;
+        match runtime.load(script).unwrap().eval() {
+            Err(Error::Limit(Limit::Timeout)) => (),
+            res => panic!("expected timeout, got {:?}", res),
+        }
+        let res = runtime
+            .load("return tostring(pcall(error, 'caught'))")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(res, Some("false".to_string()));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut runtime = RuntimeBuilder::new()
+            .limits(Limits::new().memory(1 << 20))
+            .build_default()
+            .unwrap();
+        let res = runtime
+            .load(
+                r#"
+local t = {}
+for i = 1, 1e7 do
+    t[i] = string.rep("x", 64) .. i
+end
+            "#,
+            )
+            .unwrap()
+            .eval();
+        match res {
+            Err(Error::Limit(Limit::Memory)) => (),
+            res => panic!("expected memory limit, got {:?}", res),
+        }
+        let res = runtime.load("return 'done'").unwrap().eval().unwrap();
+        assert_eq!(res, Some("done".to_string()));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut runtime = DefaultRuntime::default();
+        runtime
+            .load(
+                r#"
+TABLE = {}
+for i = 1, 10000 do
+    TABLE[i] = string.rep("x", 64) .. i
+end
+            "#,
+            )
+            .unwrap()
+            .eval()
+            .unwrap();
+        let usage = runtime.memory_usage();
+        assert!(usage.current > 10000 * 64);
+        assert!(usage.peak >= usage.current);
+
+        // with a limit the heap is also sampled during the execution
+        runtime.set_limits(Limits::new().timeout(Duration::from_secs(10)));
+        runtime
+            .load(
+                r#"
+TABLE = nil
+collectgarbage()
+local t = {}
+for i = 1, 10000 do
+    t[i] = string.rep("x", 64) .. i
+end
+t = nil
+collectgarbage()
+            "#,
+            )
+            .unwrap()
+            .eval()
+            .unwrap();
+        let usage = runtime.memory_usage();
+        assert!(usage.peak > 10000 * 64);
+        assert!(usage.peak > usage.current);
+    }
 }
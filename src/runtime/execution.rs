@@ -0,0 +1,35 @@
+use super::ResourceUsage;
+use std::time::Duration;
+
+/// The output and statistics of a single execution of a
+/// [`Runtime`](crate::prelude::Runtime)
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionResult {
+    /// The value returned by the execution, as returned by
+    /// [`Runtime::eval`](crate::prelude::Runtime::eval)
+    pub output: Option<String>,
+    /// Everything written with `print` during the execution
+    pub stdout: String,
+    /// Everything written with `warn` and `trace` during the execution,
+    /// prefixed with `[W]` and `[T]`
+    pub stderr: String,
+    /// The Zencode traceback of the statements which were run
+    pub traceback: Option<String>,
+    /// Durations of the phases of the execution
+    pub timings: Timings,
+    /// Resources used during the execution
+    pub usage: ResourceUsage,
+}
+
+/// Durations of the phases of an execution
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// Time spent parsing the source. For the
+    /// [`ZencodeRuntime`](crate::ZencodeRuntime) this is the time spent
+    /// loading scenarios and parsing the Zencode in the last `load`
+    pub parse: Duration,
+    /// Time spent running the parsed source
+    pub run: Duration,
+    /// Time spent converting the returned value to the output
+    pub encode: Duration,
+}
@@ -0,0 +1,249 @@
+use crate::{Error, Limit, Result};
+use rlua::{Context, Function, HookTriggers, Lua};
+use std::cell::Cell;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many VM instructions are executed between two limit checks
+const CHECK_INTERVAL: u32 = 1000;
+
+/// Registry key of the original `collectgarbage` function, which is used to
+/// sample the heap size even when the global is stripped or overwritten
+const COLLECTGARBAGE_KEY: &str = "zenroom_minimal.collectgarbage";
+
+/// Registry key of the flag which is set while an execution exceeded a limit
+const EXCEEDED_KEY: &str = "zenroom_minimal.limit_exceeded";
+
+/// Wraps the functions which catch errors, so that they re-raise an error
+/// while a limit is exceeded. Called with a function raising that error
+const PROTECT_CALLS: &str = r#"
+local check = ...
+local function rethrow(...)
+    check()
+    return ...
+end
+local pcall, xpcall = pcall, xpcall
+if pcall then
+    _G.pcall = function(...) return rethrow(pcall(...)) end
+end
+if xpcall then
+    _G.xpcall = function(...) return rethrow(xpcall(...)) end
+end
+if coroutine and coroutine.resume then
+    local resume = coroutine.resume
+    coroutine.resume = function(...) return rethrow(resume(...)) end
+end
+"#;
+
+/// Execution limits of a runtime. The instruction and time limits are
+/// applied to each execution separately, so a runtime which exceeded a limit
+/// can be reused afterwards. The memory limit applies to the whole Lua heap
+/// of the runtime, including loaded modules and scenarios.
+///
+/// The instruction budget is checked every 1000 instructions, so a script
+/// may execute slightly more instructions than configured. Once a limit is
+/// exceeded, `pcall`, `xpcall` and `coroutine.resume` raise the error again
+/// when they return, so it can not be caught to keep the script running.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use zenroom_minimal::Limits;
+/// let limits = Limits::new()
+///     .instructions(1_000_000)
+///     .timeout(Duration::from_millis(100))
+///     .memory(16 * 1024 * 1024);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    instructions: Option<u64>,
+    timeout: Option<Duration>,
+    memory: Option<usize>,
+}
+
+/// Resources used by an execution of a runtime
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Amount of Lua VM instructions executed, counted in steps of 1000
+    pub instructions: u64,
+    /// Lua heap usage
+    pub memory: MemoryUsage,
+}
+
+/// Lua heap usage of a runtime in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Heap size after the last execution finished
+    pub current: usize,
+    /// Largest heap size observed during the last execution. With an
+    /// instruction or time limit the heap is sampled every 1000
+    /// instructions, so short lived peaks may be missed. Without them it is
+    /// only measured before and after the execution
+    pub peak: usize,
+}
+
+impl Limits {
+    /// Create new [`Limits`] without any restrictions
+    pub fn new() -> Self {
+        Limits::default()
+    }
+
+    /// Limit the amount of Lua VM instructions which may be executed
+    pub fn instructions(mut self, instructions: u64) -> Self {
+        self.instructions = Some(instructions);
+        self
+    }
+
+    /// Limit the wall-clock time an execution may take
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the size of the Lua heap in bytes
+    pub fn memory(mut self, memory: usize) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Apply the limits which are set on the Lua VM as a whole
+    pub(crate) fn apply(&self, lua: &Lua) {
+        lua.set_memory_limit(self.memory);
+    }
+
+    /// Keep a reference to `collectgarbage` for memory accounting.
+    /// Must be called before the global is stripped from the VM
+    pub(crate) fn track_memory(ctx: Context) -> rlua::Result<()> {
+        if let Some(func) = ctx.globals().get::<_, Option<Function>>("collectgarbage")? {
+            ctx.set_named_registry_value(COLLECTGARBAGE_KEY, func)?;
+        }
+        Ok(())
+    }
+
+    /// Replace `pcall`, `xpcall` and `coroutine.resume` so that the error
+    /// of an exceeded limit can not be caught by Lua code which then keeps
+    /// running. Must be called before any Lua code is loaded
+    pub(crate) fn protect_calls(ctx: Context) -> rlua::Result<()> {
+        let check = ctx.create_function(|ctx, ()| {
+            match ctx.named_registry_value::<_, Option<bool>>(EXCEEDED_KEY)? {
+                Some(true) => Err(rlua::Error::RuntimeError(
+                    "execution limit exceeded".to_string(),
+                )),
+                _ => Ok(()),
+            }
+        })?;
+        ctx.load(PROTECT_CALLS)
+            .set_name("=protect_calls")?
+            .into_function()?
+            .call(check)
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.instructions.is_none() && self.timeout.is_none()
+    }
+
+    fn sample_memory(ctx: Context) -> Option<usize> {
+        let func = ctx
+            .named_registry_value::<_, Option<Function>>(COLLECTGARBAGE_KEY)
+            .ok()??;
+        let kilobytes: f64 = func.call("count").ok()?;
+        Some((kilobytes * 1024.0) as usize)
+    }
+
+    /// Run `f` with a hook installed on the Lua VM which aborts the
+    /// execution once a limit is exceeded and records the used resources
+    pub(crate) fn guard<F, R>(&self, lua: &Lua, usage: &Cell<ResourceUsage>, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>,
+    {
+        if self.is_unlimited() {
+            // the memory limit is enforced by the allocator, no hook needed
+            let before = lua.used_memory();
+            let res = f();
+            let current = lua.used_memory();
+            usage.set(ResourceUsage {
+                instructions: 0,
+                memory: MemoryUsage {
+                    current,
+                    peak: cmp::max(before, current),
+                },
+            });
+            return self.limit_error(res, None);
+        }
+        let interval = self
+            .instructions
+            .map(|budget| cmp::min(cmp::max(budget, 1), u64::from(CHECK_INTERVAL)) as u32)
+            .unwrap_or(CHECK_INTERVAL);
+        let budget = self.instructions;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        // the hook error may be caught and rethrown as a string by Lua code,
+        // so remember which limit was hit outside of the VM
+        let state = Arc::new(Mutex::new(HookState {
+            executed: 0,
+            peak: lua.used_memory(),
+            exceeded: None,
+        }));
+        let hook_state = state.clone();
+        lua.set_hook(
+            HookTriggers {
+                every_nth_instruction: Some(interval),
+                ..Default::default()
+            },
+            move |ctx, _| {
+                let memory = Limits::sample_memory(ctx);
+                let mut state = hook_state.lock().unwrap();
+                if let Some(memory) = memory {
+                    state.peak = cmp::max(state.peak, memory);
+                }
+                state.executed += u64::from(interval);
+                let limit = match (budget, deadline) {
+                    (Some(budget), _) if state.executed > budget => Limit::Instructions,
+                    (_, Some(deadline)) if Instant::now() >= deadline => Limit::Timeout,
+                    _ => return Ok(()),
+                };
+                state.exceeded = Some(limit);
+                ctx.set_named_registry_value(EXCEEDED_KEY, true)?;
+                Err(Error::Limit(limit).into())
+            },
+        );
+        let res = f();
+        lua.remove_hook();
+
+        let state = *state.lock().unwrap();
+        if state.exceeded.is_some() {
+            lua.context(|ctx| ctx.set_named_registry_value(EXCEEDED_KEY, false))?;
+        }
+        let current = lua.used_memory();
+        usage.set(ResourceUsage {
+            instructions: state.executed,
+            memory: MemoryUsage {
+                current,
+                peak: cmp::max(state.peak, current),
+            },
+        });
+
+        self.limit_error(res, state.exceeded)
+    }
+
+    /// Turn the error of an execution into the [`Limit`] it exceeded
+    fn limit_error<R>(&self, res: Result<R>, exceeded: Option<Limit>) -> Result<R> {
+        match (res, exceeded) {
+            (Err(_), Some(limit)) => Err(Error::Limit(limit)),
+            // allocation failures inside of a `pcall` are turned into
+            // plain runtime errors
+            (Err(Error::Lua(ref err)), None)
+                if self.memory.is_some() && err.to_string().contains("not enough memory") =>
+            {
+                Err(Error::Limit(Limit::Memory))
+            }
+            (res, _) => res,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HookState {
+    executed: u64,
+    peak: usize,
+    exceeded: Option<Limit>,
+}
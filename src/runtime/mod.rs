@@ -1,15 +1,34 @@
+mod builder;
+mod console;
 mod default;
+mod execution;
+mod limits;
+mod sandbox;
 mod zencode;
 
+pub use builder::RuntimeBuilder;
 pub use default::DefaultRuntime;
+pub use execution::{ExecutionResult, Timings};
+pub use limits::{Limits, MemoryUsage, ResourceUsage};
 pub use zencode::ZencodeRuntime;
 
-use rlua::Result;
+use crate::Result;
+use serde::de::DeserializeOwned;
 
 /// A runtime to execute interpreted source
 pub trait Runtime {
     /// Load source code into the runtime
     fn load(&mut self, source: &str) -> Result<&mut Self>;
+    /// Execute the loaded source code and return the output together with
+    /// the captured output and statistics of the execution
+    fn execute(&self) -> Result<ExecutionResult>;
     /// Evaluate the loaded source code and return some output as a String
-    fn eval(&self) -> Result<Option<String>>;
+    fn eval(&self) -> Result<Option<String>> {
+        Ok(self.execute()?.output)
+    }
+    /// Evaluate the loaded source code and deserialize the returned Lua
+    /// value directly into `T`
+    fn eval_as<T: DeserializeOwned>(&self) -> Result<T>
+    where
+        Self: Sized;
 }
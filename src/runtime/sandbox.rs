@@ -0,0 +1,38 @@
+use rlua::{Context, Result, StdLib, Table, Value};
+
+/// Globals which allow loading arbitrary code or inspecting the VM.
+/// These are removed from sandboxed runtimes after setup
+const UNSAFE_GLOBALS: &[&str] = &[
+    "collectgarbage",
+    "dofile",
+    "load",
+    "loadfile",
+    "loadstring",
+    "require",
+];
+
+/// The standard libraries loaded into a sandboxed Lua VM. Excludes
+/// `io`, `os`, `package` and `debug`
+pub(crate) fn libs() -> StdLib {
+    // TODO make static
+    let mut libs = StdLib::empty();
+    libs.insert(StdLib::BASE);
+    libs.insert(StdLib::COROUTINE);
+    libs.insert(StdLib::TABLE);
+    libs.insert(StdLib::STRING);
+    libs.insert(StdLib::UTF8);
+    libs.insert(StdLib::MATH);
+    libs
+}
+
+/// Remove the [`UNSAFE_GLOBALS`] and `string.dump` from the VM
+pub(crate) fn strip_globals(ctx: Context) -> Result<()> {
+    let globals = ctx.globals();
+    for name in UNSAFE_GLOBALS {
+        globals.set(*name, Value::Nil)?;
+    }
+    if let Some(string) = globals.get::<_, Option<Table>>("string")? {
+        string.set("dump", Value::Nil)?;
+    }
+    Ok(())
+}
@@ -1,13 +1,33 @@
-use super::Runtime;
-use crate::{prelude::*, Importer, Json, ScenarioLoader, Zencode};
-use rlua::{Lua, Result};
+use super::console::Console;
+use super::{
+    ExecutionResult, Limits, MemoryUsage, ResourceUsage, Runtime, RuntimeBuilder, Timings,
+};
+use crate::{
+    prelude::*, Error, Importer, Json, Phase, Result, ScenarioLoader, Scope, Zencode, ZencodeError,
+};
+use rlua::{Context, FromLuaMulti, Function, Lua, Table, Value};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
 
 /// Execution environment to parse Zencode source and run
-/// the Zencode against scenarios, data and keys
+/// the Zencode against scenarios, data and keys.
+///
+/// Like the [`DefaultRuntime`](crate::DefaultRuntime) the environment is
+/// sandboxed without file system or OS access. In addition, functions which
+/// load or compile Lua code (`load`, `dofile`, `string.dump`, ...) are
+/// removed, so scenarios can only define behaviour through Zencode statements.
+/// Output of `print`, `warn` and `trace` in scenarios is captured in the
+/// [`ExecutionResult`]. Use the [`RuntimeBuilder`] for a custom setup
 pub struct ZencodeRuntime {
     lua: Lua,
-    data: String,
-    keys: String,
+    data: serde_json::Value,
+    keys: serde_json::Value,
+    console: Console,
+    verbosity: u8,
+    parse_time: Duration,
+    limits: Limits,
+    usage: Cell<ResourceUsage>,
 }
 
 impl Default for ZencodeRuntime {
@@ -23,77 +43,259 @@ impl ZencodeRuntime {
     where
         L: 'static + ScenarioLinker + Sync + Send,
     {
-        let lua = Lua::default();
-        lua.context(|ctx| {
-            Importer::import_module(ctx).unwrap();
-            ctx.globals()
-                .set(
-                    ScenarioLoader::GLOBAL_VAR,
-                    loader.build_module(ctx).unwrap(),
-                )
-                .unwrap();
-            Zencode::import_module(ctx).unwrap();
-            Json::import_module(ctx).unwrap();
-            // TODO verbosity
-            ctx.load("ZEN:begin(1)").exec().unwrap();
-        });
+        RuntimeBuilder::new()
+            .preload::<Json>()
+            .scenario_loader(loader)
+            .build_zencode()
+            .unwrap()
+    }
+
+    /// Create a new [`ZencodeRuntime`] from an already set up Lua VM
+    pub(crate) fn from_lua(lua: Lua, console: Console) -> Self {
         ZencodeRuntime {
             lua,
-            data: "{}".to_string(),
-            keys: "{}".to_string(),
+            data: serde_json::Value::Object(Default::default()),
+            keys: serde_json::Value::Object(Default::default()),
+            console,
+            verbosity: 1,
+            parse_time: Duration::default(),
+            limits: Limits::default(),
+            usage: Cell::new(ResourceUsage::default()),
         }
     }
 
-    /// Load data to be passed into `ZEN:run`
+    /// Create a new [`ZencodeRuntime`] with [`Limits`] applied when loading
+    /// scenarios and running Zencode
+    pub fn with_limits<L>(loader: ScenarioLoader<L>, limits: Limits) -> Self
+    where
+        L: 'static + ScenarioLinker + Sync + Send,
+    {
+        let mut runtime = ZencodeRuntime::new(loader);
+        runtime.set_limits(limits);
+        runtime
+    }
+
+    /// Remove a module from the import cache, so that the next `import`
+    /// builds it again
+    pub fn invalidate_module(&self, identifier: &str) -> Result<()> {
+        Ok(self
+            .lua
+            .context(|ctx| Importer::invalidate(ctx, identifier))?)
+    }
+
+    /// Set the [`Limits`] applied when loading scenarios and running Zencode
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        limits.apply(&self.lua);
+        self.limits = limits;
+        self
+    }
+
+    /// Set the verbosity of Zencode, which is passed to `ZEN:begin`
+    pub fn set_verbosity(&mut self, verbosity: u8) -> Result<&mut Self> {
+        self.lua.context(|ctx| -> Result<()> {
+            let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+            let begin: Function = zen.get("begin")?;
+            begin.call::<_, ()>((zen, verbosity))?;
+            Ok(())
+        })?;
+        self.verbosity = verbosity;
+        Ok(self)
+    }
+
+    /// The verbosity of Zencode
+    pub fn verbosity(&self) -> u8 {
+        self.verbosity
+    }
+
+    /// The Zencode traceback (`ZEN_traceback`) of the statements run by the
+    /// last call to `load` or `eval`. Also available after a failed
+    /// execution to find the statement which failed
+    pub fn traceback(&self) -> Option<String> {
+        self.lua
+            .context(|ctx| ctx.globals().get::<_, Option<String>>("ZEN_traceback"))
+            .ok()
+            .and_then(|traceback| traceback)
+    }
+
+    /// Register a Zencode statement implemented in Rust, replacing a
+    /// statement with the same pattern. The quoted parameters are converted
+    /// to `A` and [`Scope`] gives access to `ACK`, `IN`, `KEYS` and `OUT`.
+    /// Statements have to be registered before calling [`Runtime::load`]
+    ///
+    /// ```
+    /// # use zenroom_minimal::{prelude::*, Phase, Result, Scope, ZencodeRuntime};
+    /// # fn main() -> Result<()> {
+    /// let mut runtime = ZencodeRuntime::default();
+    /// runtime.register_statement(
+    ///     Phase::Then,
+    ///     "print ''",
+    ///     |scope: &mut Scope, (text,): (String,)| {
+    ///         scope.out = text.to_lua(scope.context())?;
+    ///         Ok(())
+    ///     },
+    /// )?;
+    /// let res = runtime.load("Then print 'hello'")?.eval()?;
+    /// # assert_eq!(res, Some("\"hello\"".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_statement<A, F>(
+        &mut self,
+        phase: Phase,
+        pattern: &str,
+        handler: F,
+    ) -> Result<&mut Self>
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<()>,
+    {
+        self.lua
+            .context(|ctx| Zencode::register_statement(ctx, phase, pattern, handler))?;
+        Ok(self)
+    }
+
+    /// Register a condition implemented in Rust, which runs an `If` block
+    /// if `handler` returns `true`. Conditions have to be registered before
+    /// calling [`Runtime::load`]
+    pub fn register_condition<A, F>(
+        &mut self,
+        phase: Phase,
+        pattern: &str,
+        handler: F,
+    ) -> Result<&mut Self>
+    where
+        A: for<'lua> FromLuaMulti<'lua>,
+        F: 'static + Send + Sync + for<'lua> Fn(&mut Scope<'lua>, A) -> Result<bool>,
+    {
+        self.lua
+            .context(|ctx| Zencode::register_condition(ctx, phase, pattern, handler))?;
+        Ok(self)
+    }
+
+    /// The Lua heap usage of the last call to `load` or `eval`
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.usage.get().memory
+    }
+
+    /// Run the parsed Zencode within the [`Limits`] and convert the `OUT`
+    /// value returned by `ZEN:run` with `encode`
+    fn run<R, F>(&self, timings: &mut Timings, encode: F) -> Result<R>
+    where
+        F: for<'lua> FnOnce(Context<'lua>, Value<'lua>) -> Result<R>,
+    {
+        self.limits
+            .guard(&self.lua, &self.usage, || {
+                self.lua.context(|ctx| {
+                    let start = Instant::now();
+                    let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+                    let run: Function = zen.get("run")?;
+                    let data = Json::to_lua(ctx, &self.data)?;
+                    let keys = Json::to_lua(ctx, &self.keys)?;
+                    let out: Value = run.call((zen, data, keys))?;
+                    timings.run = start.elapsed();
+
+                    let start = Instant::now();
+                    let output = encode(ctx, out)?;
+                    timings.encode = start.elapsed();
+                    Ok(output)
+                })
+            })
+            .map_err(|err| match err {
+                Error::Lua(err) => Error::ZencodeRun(ZencodeError::new(err.to_string())),
+                err => err,
+            })
+    }
+
+    /// Load data to be passed into `ZEN:run` as `DATA` from a JSON string
     pub fn load_data(&mut self, data: &str) -> Result<&mut Self> {
-        // TODO validation
-        self.data = data.to_owned();
+        self.data = serde_json::from_str(data)?;
         Ok(self)
     }
 
-    /// Load keys to be passed into `ZEN:run`
+    /// Load keys to be passed into `ZEN:run` as `KEYS` from a JSON string
     pub fn load_keys(&mut self, keys: &str) -> Result<&mut Self> {
-        // TODO validation
-        self.keys = keys.to_owned();
+        self.keys = serde_json::from_str(keys)?;
+        Ok(self)
+    }
+
+    /// Load data to be passed into `ZEN:run` as `DATA` from any
+    /// serializable value
+    pub fn load_data_value<T: Serialize + ?Sized>(&mut self, data: &T) -> Result<&mut Self> {
+        self.data = serde_json::to_value(data)?;
+        Ok(self)
+    }
+
+    /// Load keys to be passed into `ZEN:run` as `KEYS` from any
+    /// serializable value
+    pub fn load_keys_value<T: Serialize + ?Sized>(&mut self, keys: &T) -> Result<&mut Self> {
+        self.keys = serde_json::to_value(keys)?;
         Ok(self)
     }
 }
 
 impl Runtime for ZencodeRuntime {
     fn load(&mut self, source: &str) -> Result<&mut Self> {
-        self.lua.context(|ctx| {
-            ctx.load(&format!(
-                r#"
-ZEN:reset()
-script = [[{}]]
-ZEN:parse(script)
-            "#,
-                source
-            ))
-            .exec()
-        })?;
+        let lua = &self.lua;
+        let start = Instant::now();
+        self.console.clear();
+        self.limits
+            .guard(lua, &self.usage, || {
+                lua.context(|ctx| -> Result<()> {
+                    let zen: Table = ctx.globals().get(Zencode::GLOBAL_VAR)?;
+                    let reset: Function = zen.get("reset")?;
+                    let parse: Function = zen.get("parse")?;
+                    reset.call::<_, ()>(zen.clone())?;
+                    parse.call::<_, ()>((zen, source))?;
+                    Ok(())
+                })
+            })
+            .map_err(|err| match err {
+                Error::Lua(err) => Error::ZencodeParse(ZencodeError::new(err.to_string())),
+                err => err,
+            })?;
+        self.parse_time = start.elapsed();
         Ok(self)
     }
 
-    fn eval(&self) -> Result<Option<String>> {
-        // TODO encoding of data and keys
-        self.lua.context(|ctx| {
-            ctx.load(&format!(
-                "return JSON.encode(ZEN:run({}, {}))",
-                self.data, self.keys
-            ))
-            .eval()
+    fn execute(&self) -> Result<ExecutionResult> {
+        let mut timings = Timings {
+            parse: self.parse_time,
+            ..Timings::default()
+        };
+        let output = self.run(&mut timings, |_, out| {
+            Ok(serde_json::to_string(&Json::from_lua(out)?)?)
+        });
+        let captured = self.console.take();
+        Ok(ExecutionResult {
+            output: Some(output?),
+            stdout: captured.stdout,
+            stderr: captured.stderr,
+            traceback: self.traceback(),
+            timings,
+            usage: self.usage.get(),
         })
     }
+
+    fn eval_as<T: DeserializeOwned>(&self) -> Result<T> {
+        let res = self.run(&mut Timings::default(), |_, out| {
+            rlua_serde::from_value(out).map_err(|err| Error::Encoding(err.to_string()))
+        });
+        self.console.clear();
+        res
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{FileScenarioLinker, ScenarioLoader};
+    use crate::{
+        EmbeddedScenarioLinker, FileScenarioLinker, Limit, Phase, ScenarioLoader,
+        WatchingScenarioLinker,
+    };
     use rand::{prelude::*, thread_rng};
     use std::fs::{remove_file, File};
     use std::io::prelude::*;
+    use std::time::Duration;
 
     fn random_scenario(len: usize) -> String {
         thread_rng()
@@ -102,6 +304,46 @@ mod tests {
             .collect()
     }
 
+    const HELLO_SCENARIO: &str = r#"
+Given("that my name is ''", function(name)
+    ACK.name = name
+end)
+
+Then("say hello", function()
+    OUT = "Hello, " .. ACK.name .. "!"
+end)
+"#;
+
+    fn write_scenario(source: &str) -> (String, String) {
+        // TODO make this windows compatible
+        let scenario = random_scenario(10);
+        let filename = format!("/tmp/zencode_{}.lua", scenario);
+        File::create(&filename)
+            .and_then(|mut file| file.write_all(source.as_ref()))
+            .unwrap();
+        (scenario, filename)
+    }
+
+    fn hello_with_name(name: &str) -> Option<String> {
+        let (scenario, filename) = write_scenario(HELLO_SCENARIO);
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+Given that my name is '{}'
+Then say hello
+        "#,
+                scenario, name
+            ))
+            .unwrap()
+            .eval()
+            .unwrap();
+        remove_file(filename).unwrap();
+        res
+    }
+
     #[test]
     fn empty() {
         let mut runtime = ZencodeRuntime::default();
@@ -153,6 +395,33 @@ And print all data
         remove_file(filename).unwrap();
     }
 
+    #[test]
+    fn captured_output() {
+        let (scenario, filename) = write_scenario(&format!(
+            r#"{}
+Then("print all data", function()
+    print(OUT)
+    warn("printed")
+end)
+"#,
+            HELLO_SCENARIO
+        ));
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res = runtime
+            .load(&format!(
+                "Scenario '{}'\nGiven that my name is 'Julian'\nThen say hello\nAnd print all data",
+                scenario
+            ))
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(Some("\"Hello, Julian!\"".to_string()), res.output);
+        assert_eq!("Hello, Julian!\n", res.stdout);
+        assert_eq!("[W] printed\n", res.stderr);
+        remove_file(filename).unwrap();
+    }
+
     #[test]
     fn addition() {
         // TODO make this windows compatible
@@ -181,7 +450,7 @@ end)
                 )
             })
             .unwrap();
-        let data = "{a = 1, b = 2}";
+        let data = r#"{"a": 1, "b": 2}"#;
         let res = runtime
             .load_data(data)
             .unwrap()
@@ -200,4 +469,473 @@ And print all data
         assert_eq!(Some("3".to_string()), res);
         remove_file(filename).unwrap();
     }
+
+    #[test]
+    fn serialized_data() {
+        #[derive(Serialize)]
+        struct Operands {
+            a: i64,
+            b: i64,
+        }
+
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let scenario = random_scenario(10);
+        let filename = format!("/tmp/zencode_{}.lua", scenario);
+        File::create(&filename)
+            .and_then(|mut file| {
+                file.write_all(
+                    r#"
+Given("that I want to add '' with ''", function(a, b)
+    ACK.left = IN[a]
+    ACK.right = IN[b]
+end)
+
+Then("do addition", function()
+    OUT = ACK.left + ACK.right
+end)
+"#
+                    .as_ref(),
+                )
+            })
+            .unwrap();
+        let res = runtime
+            .load_data_value(&Operands { a: 2, b: 5 })
+            .unwrap()
+            .load(&format!(
+                r#"
+Scenario '{}'
+Given that I want to add 'a' with 'b'
+Then do addition
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(Some("7".to_string()), res);
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn eval_as() {
+        let (scenario, filename) = write_scenario(HELLO_SCENARIO);
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res: String = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+Given that my name is 'Julian'
+Then say hello
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval_as()
+            .unwrap();
+        assert_eq!("Hello, Julian!", res);
+        match runtime.eval_as::<u32>() {
+            Err(Error::Encoding(_)) => (),
+            res => panic!("expected encoding error, got {:?}", res),
+        }
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn rust_statements() {
+        let mut runtime = RuntimeBuilder::new()
+            .zencode(Zencode::new().statement(
+                Phase::Given,
+                "that I want to add '' with ''",
+                |scope: &mut Scope, (a, b): (String, String)| {
+                    let a: i64 = scope.input.get(a)?;
+                    let b: i64 = scope.input.get(b)?;
+                    scope.ack.set("sum", a + b)?;
+                    Ok(())
+                },
+            ))
+            .build_zencode()
+            .unwrap();
+        runtime
+            .register_statement(Phase::Then, "print the sum", |scope: &mut Scope, ()| {
+                scope.out = scope.ack.get("sum")?;
+                Ok(())
+            })
+            .unwrap();
+        let res = runtime
+            .load_data(r#"{"a": 1, "b": 2}"#)
+            .unwrap()
+            .load("Given that I want to add 'a' with 'b'\nThen print the sum")
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(Some("3".to_string()), res);
+    }
+
+    #[test]
+    fn conditions() {
+        let mut runtime = ZencodeRuntime::default();
+        runtime
+            .register_condition(
+                Phase::When,
+                "'<data>' is signed",
+                |_: &mut Scope, (document,): (Table,)| {
+                    Ok(document.get::<_, Option<String>>("signature")?.is_some())
+                },
+            )
+            .unwrap()
+            .register_statement(
+                Phase::When,
+                "I accept '<data>'",
+                |scope: &mut Scope, (document,): (Table,)| {
+                    let accepted: Table = scope.ack.get("accepted")?;
+                    let name: String = document.get("name")?;
+                    accepted.set(accepted.len()? + 1, name)?;
+                    Ok(())
+                },
+            )
+            .unwrap()
+            .register_statement(
+                Phase::Given,
+                "nothing is accepted",
+                |scope: &mut Scope, ()| {
+                    scope.ack.set("accepted", scope.context().create_table()?)?;
+                    Ok(())
+                },
+            )
+            .unwrap()
+            .register_statement(
+                Phase::Then,
+                "print the accepted",
+                |scope: &mut Scope, ()| {
+                    scope.out = scope.ack.get("accepted")?;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        let data = r#"{"documents": [{"name": "a", "signature": "x"}, {"name": "b"}]}"#;
+        let res = runtime
+            .load_data(data)
+            .unwrap()
+            .load(
+                r#"
+Given nothing is accepted
+Foreach 'document' in 'documents'
+If 'document' is signed
+When I accept 'document'
+EndIf
+EndForeach
+Then print the accepted
+"#,
+            )
+            .unwrap()
+            .eval_as::<Vec<String>>()
+            .unwrap();
+        assert_eq!(res, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn scenario_dependencies() {
+        let (base, base_file) = write_scenario(
+            r#"
+BASE_LOADS = (BASE_LOADS or 0) + 1
+Given("I have nothing", function() end)
+"#,
+        );
+        let (dependent, dependent_file) = write_scenario(&format!(
+            r#"
+load_scenario("{}")
+Then("print the loads", function()
+    OUT = BASE_LOADS
+end)
+"#,
+            base
+        ));
+        let script = format!(
+            "Scenario '{}'\nScenario '{}'\nGiven I have nothing\nThen print the loads",
+            dependent, base
+        );
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        for _ in 0..2 {
+            let res = runtime.load(&script).unwrap().eval().unwrap();
+            assert_eq!(Some("1".to_string()), res);
+        }
+        remove_file(base_file).unwrap();
+        remove_file(dependent_file).unwrap();
+    }
+
+    #[test]
+    fn reloaded_scenario() {
+        let (scenario, filename) = write_scenario(HELLO_SCENARIO);
+        let linker = WatchingScenarioLinker::new(FileScenarioLinker::new("/tmp"))
+            .on_error(|scenario, err| panic!("reloading {} failed: {}", scenario, err));
+        let mut runtime = ZencodeRuntime::new(ScenarioLoader::new(linker));
+        let script = format!(
+            "Scenario '{}'\nGiven that my name is 'Alice'\nThen say hello",
+            scenario
+        );
+        let res = runtime.load(&script).unwrap().eval().unwrap();
+        assert_eq!(Some("\"Hello, Alice!\"".to_string()), res);
+
+        let source = HELLO_SCENARIO.replace("Hello, ", "Good morning, ");
+        File::create(&filename)
+            .and_then(|mut file| file.write_all(source.as_ref()))
+            .unwrap();
+        let res = runtime.load(&script).unwrap().eval().unwrap();
+        assert_eq!(Some("\"Good morning, Alice!\"".to_string()), res);
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn pinned_scenario() {
+        let source = format!("-- version: 1.0\n{}", HELLO_SCENARIO);
+        let (scenario, filename) = write_scenario(&source);
+        let sha256 = crate::util::sha256_hex(source.as_bytes());
+        let script = |pin: &str| {
+            format!(
+                "Scenario '{}' {}\nGiven that my name is 'Julian'\nThen say hello",
+                scenario, pin
+            )
+        };
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res = runtime
+            .load(&script(&format!("version '1.0' sha256 '{}'", sha256)))
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(Some("\"Hello, Julian!\"".to_string()), res);
+
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        match runtime.load(&script("version '1.1'")) {
+            Err(Error::ScenarioMismatch(ref msg)) => assert!(msg.contains("'1.1'")),
+            Err(err) => panic!("expected scenario mismatch, got {:?}", err),
+            Ok(_) => panic!("expected scenario mismatch"),
+        }
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn embedded_scenarios() {
+        let linker = EmbeddedScenarioLinker::new(&[(
+            "hello",
+            include_str!("../../lua/examples/scenarios/zencode_hello.lua"),
+        )]);
+        let mut runtime = ZencodeRuntime::new(ScenarioLoader::new(linker));
+        let res = runtime
+            .load(include_str!("../../lua/examples/helloworld.zencode"))
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(Some("\"Hello, Julian!\"".to_string()), res.output);
+    }
+
+    #[test]
+    fn traceback() {
+        let (scenario, filename) = write_scenario(
+            r#"
+Given("nothing", function() end)
+
+Then("fail", function()
+    error("failed on purpose")
+end)
+"#,
+        );
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = RuntimeBuilder::new()
+            .scenario_loader(loader)
+            .verbosity(2)
+            .build_zencode()
+            .unwrap();
+        assert_eq!(runtime.verbosity(), 2);
+        runtime.set_verbosity(3).unwrap();
+        assert_eq!(runtime.verbosity(), 3);
+        let res = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+Given nothing
+Then fail
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval();
+        match res {
+            Err(Error::ZencodeRun(ref err)) => {
+                assert_eq!(err.line, Some(4));
+                assert_eq!(err.statement, Some("Then fail".to_string()));
+                assert_eq!(err.phase, Some(Phase::Then));
+                assert_eq!(err.scenario, Some(scenario.clone()));
+            }
+            res => panic!("expected zencode error, got {:?}", res),
+        }
+        let traceback = runtime.traceback().unwrap();
+        assert!(traceback.contains("Then fail"));
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn unknown_statement() {
+        let (scenario, filename) = write_scenario(HELLO_SCENARIO);
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res = runtime.load(&format!(
+            r#"
+Scenario '{}'
+Given that my name is 'Julian'
+Then say helo
+        "#,
+            scenario
+        ));
+        match res {
+            Err(Error::ZencodeParse(ref err)) => {
+                assert_eq!(err.line, Some(4));
+                assert_eq!(err.statement, Some("Then say helo".to_string()));
+                assert_eq!(err.phase, Some(Phase::Then));
+                assert_eq!(err.scenario, Some(scenario.clone()));
+                assert_eq!(err.suggestion, Some("say hello".to_string()));
+            }
+            Err(err) => panic!("expected zencode parse error, got {:?}", err),
+            Ok(_) => panic!("expected zencode parse error"),
+        }
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn data_is_not_lua() {
+        let mut runtime = ZencodeRuntime::default();
+        match runtime.load_data("{a = os.exit(1)}") {
+            Err(Error::Encoding(_)) => (),
+            _ => panic!("lua table literals should be rejected"),
+        }
+        match runtime.load_keys("}) os.exit(1) --") {
+            Err(Error::Encoding(_)) => (),
+            _ => panic!("lua source should be rejected"),
+        }
+    }
+
+    #[test]
+    fn long_brackets_are_text() {
+        assert_eq!(Some("\"Hello, ]]!\"".to_string()), hello_with_name("]]"));
+        assert_eq!(
+            Some("\"Hello, ]==]!\"".to_string()),
+            hello_with_name("]==]")
+        );
+    }
+
+    #[test]
+    fn embedded_lua_is_text() {
+        let (scenario, filename) = write_scenario(HELLO_SCENARIO);
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let script = format!(
+            r#"
+Scenario '{}'
+Given that my name is 'Julian'
+]] INJECTED = true script = [[
+]==] INJECTED = true --[==[
+Then say hello
+        "#,
+            scenario
+        );
+        match runtime.load(&script) {
+            Err(Error::ZencodeParse(err)) => assert_eq!(err.line, Some(4)),
+            Err(err) => panic!("expected parse error, got {}", err),
+            Ok(_) => panic!("expected parse error"),
+        }
+        let injected: Option<bool> = runtime
+            .lua
+            .context(|ctx| ctx.globals().get("INJECTED"))
+            .unwrap();
+        assert_eq!(injected, None);
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn sandboxed() {
+        let (scenario, filename) = write_scenario(
+            r#"
+Then("list reachable globals", function()
+    local names = {
+        "io", "os", "package", "debug", "require", "dofile", "loadfile",
+        "load", "loadstring", "collectgarbage"
+    }
+    local reachable = {}
+    for _, name in ipairs(names) do
+        if _G[name] ~= nil then
+            table.insert(reachable, name)
+        end
+    end
+    if string.dump ~= nil then
+        table.insert(reachable, "string.dump")
+    end
+    OUT = table.concat(reachable, ",")
+end)
+"#,
+        );
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        let res = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+Then list reachable globals
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(Some("\"\"".to_string()), res);
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn limits() {
+        let (scenario, filename) = write_scenario(
+            r#"
+When("I loop forever", function()
+    while true do end
+end)
+
+Then("say done", function()
+    OUT = "done"
+end)
+"#,
+        );
+        let loader = ScenarioLoader::new(FileScenarioLinker::new("/tmp"));
+        let mut runtime = ZencodeRuntime::new(loader);
+        runtime.set_limits(Limits::new().timeout(Duration::from_millis(50)));
+        let res = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+When I loop forever
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval();
+        match res {
+            Err(Error::Limit(Limit::Timeout)) => (),
+            res => panic!("expected timeout, got {:?}", res),
+        }
+        let res = runtime
+            .load(&format!(
+                r#"
+Scenario '{}'
+Then say done
+        "#,
+                scenario
+            ))
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(Some("\"done\"".to_string()), res);
+        remove_file(filename).unwrap();
+    }
 }
@@ -1,14 +1,23 @@
-use rlua::{Error, Result};
+use crate::Result;
 
+use ring::digest;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
 /// Utility to read a file to a [`String`] from a path
 pub fn read_file<P: AsRef<Path>>(path: P) -> Result<String> {
-    let mut file = File::open(path).map_err(|e| Error::RuntimeError(e.to_string()))?;
+    let mut file = File::open(path)?;
     let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    file.read_to_string(&mut contents)?;
     Ok(contents)
 }
+
+/// Utility to hash bytes with SHA-256, encoded as lower case hex
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    digest::digest(&digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}